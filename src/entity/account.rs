@@ -1,26 +1,96 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use super::RoundingMode;
+use crate::{Result, TransactionError};
+
 /// Represents the Accounts of the clients transacting with the system.
+/// The balances can only be read from outside of the crate, so that
+/// they only ever change through the operations below.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Account {
     /// Unique identifier for the Client
-    pub client: u16,
+    client: u16,
     /// Represents the available amount in the Account.
-    pub available: f32,
+    available: f32,
     /// Represents the held amount in the Account.
-    pub held: f32,
+    held: f32,
     /// Represents the total amount in the Account.
-    pub total: f32,
+    total: f32,
     /// Boolean value to represent if the Account is locked or not.
-    pub locked: bool,
+    locked: bool,
+    /// Currency of the money in the Account, taken from the first
+    /// deposit which specified one.
+    #[serde(default)]
+    currency: Option<String>,
 }
 
 /// All implementations for different transactions return a new Account
 /// rather than mutating the existing account.
 impl Account {
+    /// Opens an empty, unlocked Account for the client.
+    pub(crate) fn new(client: u16) -> Self {
+        Account {
+            client,
+            available: 0.0,
+            held: 0.0,
+            total: 0.0,
+            locked: false,
+            currency: None,
+        }
+    }
+
+    /// Builds an Account in any state, including ones the operations
+    /// could never reach, so that tests can set up corrupted ledgers.
+    #[cfg(test)]
+    pub(crate) fn from_parts(client: u16, available: f32, held: f32, total: f32, locked: bool) -> Self {
+        Account { client, available, held, total, locked, currency: None }
+    }
+
+    /// Unique identifier for the Client
+    pub fn client(&self) -> u16 {
+        self.client
+    }
+
+    /// Amount that can be withdrawn or transferred.
+    pub fn available(&self) -> f32 {
+        self.available
+    }
+
+    /// Amount held by open disputes.
+    pub fn held(&self) -> f32 {
+        self.held
+    }
+
+    /// Sum of the available and held amounts.
+    pub fn total(&self) -> f32 {
+        self.total
+    }
+
+    /// Whether a chargeback locked the Account.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Currency of the money in the Account, if it is tagged with one.
+    pub fn currency(&self) -> Option<&str> {
+        self.currency.as_deref()
+    }
+
+    /// Tags an untagged Account with the given currency. An Account
+    /// keeps the currency it was tagged with first.
+    pub fn tagged(&self, currency: Option<&str>) -> Self {
+        Account {
+            currency: self.currency.clone().or_else(|| currency.map(String::from)),
+            ..self.clone()
+        }
+    }
+
     /// Increments available and total amount for an account.
     pub fn deposit(&self, amount: f32) -> Self {
         Account {
             available: self.available + amount,
             total: self.total + amount,
-            ..*self
+            ..self.clone()
         }
     }
 
@@ -29,7 +99,16 @@ impl Account {
         Account {
             available: self.available - amount,
             total: self.total - amount,
-            ..*self
+            ..self.clone()
+        }
+    }
+
+    /// Changes available and total amount for an account by a signed amount.
+    pub fn adjust(&self, amount: f32) -> Self {
+        Account {
+            available: self.available + amount,
+            total: self.total + amount,
+            ..self.clone()
         }
     }
 
@@ -39,7 +118,7 @@ impl Account {
         Account {
             available: self.available - amount,
             held: self.held + amount,
-            ..*self
+            ..self.clone()
         }
     }
 
@@ -49,7 +128,7 @@ impl Account {
         Account {
             available: self.available + amount,
             held: self.held - amount,
-            ..*self
+            ..self.clone()
         }
     }
 
@@ -59,18 +138,239 @@ impl Account {
             held: self.held - amount,
             total: self.total - amount,
             locked: true,
-            ..*self
+            ..self.clone()
+        }
+    }
+
+    /// Reverses the disputed transaction without locking the Account.
+    pub fn soft_chargeback(&self, amount: f32) -> Self {
+        Account {
+            held: self.held - amount,
+            total: self.total - amount,
+            ..self.clone()
+        }
+    }
+
+    /// Holds the amount of a disputed withdrawal. The withdrawn money is
+    /// provisionally added back to the total, but can't be spent until
+    /// the dispute is settled.
+    pub fn dispute_withdrawal(&self, amount: f32) -> Self {
+        Account {
+            held: self.held + amount,
+            total: self.total + amount,
+            ..self.clone()
+        }
+    }
+
+    /// The disputed withdrawal stands, so the held amount is removed again.
+    pub fn resolve_withdrawal(&self, amount: f32) -> Self {
+        Account {
+            held: self.held - amount,
+            total: self.total - amount,
+            ..self.clone()
+        }
+    }
+
+    /// Reverses the disputed withdrawal by returning the held amount to
+    /// the available balance, and locks the Account.
+    pub fn chargeback_withdrawal(&self, amount: f32) -> Self {
+        Account {
+            available: self.available + amount,
+            held: self.held - amount,
+            locked: true,
+            ..self.clone()
+        }
+    }
+
+    /// Reverses the disputed withdrawal without locking the Account.
+    pub fn soft_chargeback_withdrawal(&self, amount: f32) -> Self {
+        Account {
+            available: self.available + amount,
+            held: self.held - amount,
+            ..self.clone()
+        }
+    }
+
+    /// Re-credits a charged back amount and unlocks the Account.
+    pub fn appeal(&self, amount: f32) -> Self {
+        Account {
+            available: self.available + amount,
+            total: self.total + amount,
+            locked: false,
+            ..self.clone()
+        }
+    }
+
+    /// Unlocks the Account so that it can transact again.
+    pub fn unlock(&self) -> Self {
+        Account {
+            locked: false,
+            ..self.clone()
         }
     }
 
+    /// Checked counterpart of `deposit`, see `checked`.
+    pub fn try_deposit(&self, amount: f32) -> Result<Self> {
+        self.deposit(amount).checked()
+    }
+
+    /// Checked counterpart of `withdrawal`, see `checked`.
+    pub fn try_withdrawal(&self, amount: f32) -> Result<Self> {
+        self.withdrawal(amount).checked()
+    }
+
+    /// Checked counterpart of `adjust`, see `checked`.
+    pub fn try_adjust(&self, amount: f32) -> Result<Self> {
+        self.adjust(amount).checked()
+    }
+
+    /// Checked counterpart of `dispute`, see `checked`.
+    pub fn try_dispute(&self, amount: f32) -> Result<Self> {
+        self.dispute(amount).checked()
+    }
+
+    /// Checked counterpart of `resolve`, see `checked`.
+    pub fn try_resolve(&self, amount: f32) -> Result<Self> {
+        self.resolve(amount).checked()
+    }
+
+    /// Checked counterpart of `chargeback`, see `checked`.
+    pub fn try_chargeback(&self, amount: f32) -> Result<Self> {
+        self.chargeback(amount).checked()
+    }
+
+    /// Checked counterpart of `soft_chargeback`, see `checked`.
+    pub fn try_soft_chargeback(&self, amount: f32) -> Result<Self> {
+        self.soft_chargeback(amount).checked()
+    }
+
+    /// Checks the result of an operation. A balance that overflowed to
+    /// infinity, or was given a non-finite amount, fails with `AmountOutOfRange`,
+    /// and a held amount which went below zero with `InsufficientHeldFunds`.
+    /// Available and total may go below zero, since overdrafts are allowed.
+    pub(crate) fn checked(self) -> Result<Self> {
+        if !(self.available.is_finite() && self.held.is_finite() && self.total.is_finite()) {
+            return Err(TransactionError::AmountOutOfRange);
+        }
+        let tolerance = 0.0001_f32.max(self.total.abs() * f32::EPSILON * 4.0);
+        if self.held < -tolerance {
+            return Err(TransactionError::InsufficientHeldFunds);
+        }
+        Ok(self)
+    }
+
+    /// Rounds the balances half away from zero to the given number of decimals.
+    pub(crate) fn rounded(&self, decimals: u32) -> Self {
+        let factor = 10_f32.powi(decimals as i32);
+        let round = |value: f32| (value * factor).round() / factor;
+        Account { available: round(self.available), held: round(self.held), total: round(self.total), ..self.clone() }
+    }
+
+    /// Checks that `total == available + held`, allowing for the
+    /// rounding error of f32 arithmetic on amounts of this size.
+    pub fn is_balanced(&self) -> bool {
+        let tolerance = 0.0001_f32.max(self.total.abs() * f32::EPSILON * 4.0);
+        (self.available + self.held - self.total).abs() <= tolerance
+    }
+
     /// Prints values of the account to STD.
     pub fn print(&self) {
-        println!("{},{:.4},{:.4},{:.4},{}",
-                 self.client,
-                 self.available,
-                 self.held,
-                 self.total,
-                 self.locked
-        );
+        println!("{}", self);
+    }
+
+    /// Formats the Account as a CSV row of the report, rounding
+    /// money to four decimals with the given rounding mode.
+    pub fn to_row(&self, rounding: RoundingMode) -> String {
+        format!("{},{},{},{},{}",
+                self.client,
+                rounding.format(self.available, 4),
+                rounding.format(self.held, 4),
+                rounding.format(self.total, 4),
+                self.locked
+        )
+    }
+}
+
+/// Formats the Account as a CSV row of the report.
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_row(RoundingMode::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_format_account_as_a_report_row() {
+        let account = Account::from_parts(1, 1.5, 0.25, 1.75, true);
+
+        assert_eq!("1,1.5000,0.2500,1.7500,true", format!("{}", account));
+    }
+
+    #[test]
+    fn should_detect_an_unbalanced_account() {
+        let account = Account::from_parts(1, 1.5, 0.25, 1.75, false);
+
+        assert!(account.is_balanced());
+        assert!(account.dispute(0.5).resolve(0.25).is_balanced());
+        assert!(!Account::from_parts(1, 1.5, 0.25, 2.0, false).is_balanced());
+    }
+
+    #[test]
+    fn should_expose_balances_through_accessors() {
+        let account = Account::new(3).deposit(2.0).dispute(0.5);
+
+        assert_eq!(3, account.client());
+        assert_eq!(1.5, account.available());
+        assert_eq!(0.5, account.held());
+        assert_eq!(2.0, account.total());
+        assert!(!account.locked());
+        assert!(account.chargeback(0.5).locked());
+    }
+
+    #[test]
+    fn should_compare_whole_accounts() {
+        let account = Account::new(1).deposit(2.0).dispute(0.5);
+
+        assert_eq!(Account::from_parts(1, 1.5, 0.5, 2.0, false), account);
+        assert_eq!(account.clone(), account);
+        assert_ne!(account.chargeback(0.5), account);
+    }
+
+    #[test]
+    fn should_keep_the_account_unlocked_on_a_soft_chargeback() {
+        let account = Account::new(1).deposit(2.0).dispute(0.5).soft_chargeback(0.5);
+
+        assert_eq!(Account::from_parts(1, 1.5, 0.0, 1.5, false), account);
+    }
+
+    #[test]
+    fn should_reject_balances_which_overflow() {
+        let account = Account::from_parts(1, f32::MAX, 0.0, f32::MAX, false);
+
+        assert_eq!(Err(TransactionError::AmountOutOfRange), account.try_deposit(f32::MAX));
+        assert_eq!(Err(TransactionError::AmountOutOfRange), account.try_adjust(f32::INFINITY));
+        assert_eq!(Err(TransactionError::AmountOutOfRange), account.try_dispute(f32::NAN));
+        assert_eq!(Ok(account.deposit(1.0)), account.try_deposit(1.0));
+    }
+
+    #[test]
+    fn should_reject_balances_which_underflow() {
+        let account = Account::from_parts(1, -f32::MAX, 0.0, -f32::MAX, false);
+
+        assert_eq!(Err(TransactionError::AmountOutOfRange), account.try_withdrawal(f32::MAX));
+        assert_eq!(Ok(account.withdrawal(1.0)), account.try_withdrawal(1.0));
+    }
+
+    #[test]
+    fn should_reject_held_amounts_below_zero() {
+        let account = Account::from_parts(1, 1.0, 0.5, 1.5, false);
+
+        assert_eq!(Err(TransactionError::InsufficientHeldFunds), account.try_resolve(1.0));
+        assert_eq!(Err(TransactionError::InsufficientHeldFunds), account.try_chargeback(1.0));
+        assert_eq!(Err(TransactionError::InsufficientHeldFunds), account.try_soft_chargeback(1.0));
+        assert_eq!(Ok(account.resolve(0.5)), account.try_resolve(0.5));
     }
 }
\ No newline at end of file
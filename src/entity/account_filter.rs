@@ -0,0 +1,24 @@
+use super::Account;
+
+/// Decides which Accounts are included in a report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccountFilter {
+    /// Includes every Account. This is the default.
+    #[default]
+    All,
+    /// Only includes Accounts locked by a chargeback.
+    LockedOnly,
+    /// Only includes Accounts which are not locked.
+    ActiveOnly,
+}
+
+impl AccountFilter {
+    /// Whether the Account is included by the filter.
+    pub fn matches(&self, account: &Account) -> bool {
+        match self {
+            AccountFilter::All => true,
+            AccountFilter::LockedOnly => account.locked(),
+            AccountFilter::ActiveOnly => !account.locked(),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use serde::Serialize;
+use super::{Account, RoundingMode};
+
+/// Row of the final report for a single Account. Money is kept as
+/// already rounded strings so every row has the same number of decimals.
+/// The field order is the column order of the report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountReportRow {
+    /// Unique identifier for the Client
+    pub client: u16,
+    /// Available amount, rounded to the report's decimals.
+    pub available: String,
+    /// Held amount, rounded to the report's decimals.
+    pub held: String,
+    /// Total amount, rounded to the report's decimals.
+    pub total: String,
+    /// Whether the Account is locked or not.
+    pub locked: bool,
+}
+
+impl AccountReportRow {
+    /// Column names of the report, matching the serialized field names.
+    /// Written on their own so that a report without Accounts still has a header.
+    pub const HEADER: [&'static str; 5] = ["client", "available", "held", "total", "locked"];
+
+    /// Builds the row for the Account, rounding money to `decimals`
+    /// decimals with the given mode.
+    pub fn new(account: &Account, rounding: RoundingMode, decimals: usize) -> Self {
+        AccountReportRow {
+            client: account.client(),
+            available: rounding.format(account.available(), decimals),
+            held: rounding.format(account.held(), decimals),
+            total: rounding.format(account.total(), decimals),
+            locked: account.locked(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_serialize_rows_with_a_header() {
+        let account = Account::from_parts(7, 1.5, 0.25, 1.75, true);
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(AccountReportRow::new(&account, RoundingMode::default(), 4)).unwrap();
+
+        let report = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        assert_eq!("client,available,held,total,locked\n7,1.5000,0.2500,1.7500,true\n", report);
+        assert_eq!(AccountReportRow::HEADER.join(","), report.lines().next().unwrap());
+    }
+
+    #[test]
+    fn should_format_money_with_the_given_decimals() {
+        let account = Account::from_parts(1, 12.5, 0.0, 12.5, false);
+        let row = |decimals| AccountReportRow::new(&account, RoundingMode::HalfUp, decimals);
+
+        assert_eq!("13", row(0).available);
+        assert_eq!("12.50", row(2).available);
+        assert_eq!("12.5000", row(4).available);
+    }
+}
\ No newline at end of file
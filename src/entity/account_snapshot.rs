@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use super::Account;
+
+/// Represents the state of an Account right after a transaction
+/// was applied to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    /// Unique identifier for the Client
+    pub client: u16,
+    /// Represents the available amount in the Account.
+    pub available: f32,
+    /// Represents the held amount in the Account.
+    pub held: f32,
+    /// Represents the total amount in the Account.
+    pub total: f32,
+    /// Boolean value to represent if the Account is locked or not.
+    pub locked: bool,
+}
+
+impl From<&Account> for AccountSnapshot {
+    fn from(account: &Account) -> Self {
+        AccountSnapshot {
+            client: account.client(),
+            available: account.available(),
+            held: account.held(),
+            total: account.total(),
+            locked: account.locked(),
+        }
+    }
+}
\ No newline at end of file
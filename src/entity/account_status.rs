@@ -0,0 +1,13 @@
+/// State of a client's Account as far as transacting goes, see
+/// `TransactionService::account_status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountStatus {
+    /// The client doesn't have an Account.
+    Missing,
+    /// The Account can transact.
+    Active,
+    /// The Account was locked by a chargeback and has to be unlocked.
+    Locked,
+    /// The Account was frozen by an operator and has to be thawed.
+    Frozen,
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use super::{AccountSnapshot, TransactionType};
+
+/// Represents a change that was applied to an Account,
+/// kept in the audit log of the `TransactionService`. A log read back
+/// from JSON can be passed to `TransactionService::replay`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A transaction record was applied.
+    Transaction {
+        /// Type of the transaction that was applied.
+        #[serde(rename = "type")]
+        _type: TransactionType,
+        /// Client the transaction was made for.
+        client: u16,
+        /// Transaction that was applied.
+        tx: u32,
+        /// Amount specified in the transaction, if any.
+        amount: Option<f32>,
+        /// Client receiving the amount of a transfer.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<u16>,
+        /// Currency specified in the transaction, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+        /// Free-form reference given with the transaction, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reference: Option<String>,
+        /// Time the transaction was made at, in unix milliseconds, if known.
+        timestamp: Option<i64>,
+        /// State of the Account after the transaction was applied.
+        account: AccountSnapshot,
+    },
+    /// Interest was credited to an Account.
+    InterestAccrued {
+        /// Client the interest was credited to.
+        client: u16,
+        /// Rate the interest was calculated with.
+        rate: f32,
+        /// Interest that was credited.
+        amount: f32,
+        /// State of the Account after the interest was credited.
+        account: AccountSnapshot,
+    },
+    /// The latest deposit or withdrawal was undone.
+    Undone {
+        /// Client the transaction was made for.
+        client: u16,
+        /// Transaction that was undone.
+        tx: u32,
+        /// State of the Account after the transaction was undone.
+        account: AccountSnapshot,
+    },
+    /// Part of the available funds of an Account were put on hold.
+    HoldPlaced {
+        /// Client the hold was placed for.
+        client: u16,
+        /// Hold that was placed.
+        tx: u32,
+        /// Amount that was put on hold.
+        amount: f32,
+        /// State of the Account after the hold was placed.
+        account: AccountSnapshot,
+    },
+    /// The funds of a hold were returned to the available balance.
+    HoldReleased {
+        /// Client the hold was placed for.
+        client: u16,
+        /// Hold that was released.
+        tx: u32,
+        /// State of the Account after the hold was released.
+        account: AccountSnapshot,
+    },
+    /// The funds of a hold were taken from the Account.
+    HoldCaptured {
+        /// Client the hold was placed for.
+        client: u16,
+        /// Hold that was captured.
+        tx: u32,
+        /// State of the Account after the hold was captured.
+        account: AccountSnapshot,
+    },
+    /// A dispute pushed the held amount of an Account above the
+    /// configured alert threshold. The dispute itself still went through.
+    HeldThresholdExceeded {
+        /// Client whose held amount crossed the threshold.
+        client: u16,
+        /// Held amount after the dispute.
+        held: f32,
+    },
+    /// A chargeback locked the Account, so the client can't transact
+    /// until it is unlocked.
+    AccountLocked {
+        /// Client whose Account was locked.
+        client: u16,
+        /// Chargeback that locked the Account.
+        tx: u32,
+    },
+}
\ No newline at end of file
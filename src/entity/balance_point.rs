@@ -0,0 +1,15 @@
+use serde::Serialize;
+
+/// Balances of a client's Account right after one of their
+/// transactions was applied, as a point of their balance history.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BalancePoint {
+    /// Transaction that was applied.
+    pub tx: u32,
+    /// Available amount after the transaction.
+    pub available: f32,
+    /// Held amount after the transaction.
+    pub held: f32,
+    /// Total amount after the transaction.
+    pub total: f32,
+}
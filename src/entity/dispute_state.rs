@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Represents where a disputed transaction is in the dispute lifecycle.
+/// A transaction that was never disputed has no state. The allowed
+/// transitions are:
+/// * no state/ `Resolved` -> `Disputed` through a dispute. `ChargedBack` -> `Disputed`
+///   is allowed as well for the part of the amount which was not charged back.
+/// * `Disputed` -> `Resolved` through a resolve.
+/// * `Disputed` -> `ChargedBack` through a chargeback or a soft chargeback.
+/// * `Resolved` -> `Disputed` through a reopen, holding the same amount again.
+/// * `ChargedBack` -> `Appealed` through a successful appeal, which is final.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DisputeState {
+    /// The dispute is open and holds the given amount.
+    Disputed(f32),
+    /// The dispute was resolved and the given held amount released.
+    /// The transaction can be disputed again or reopened.
+    Resolved(f32),
+    /// The held amount was reversed, and unless it was a soft chargeback
+    /// the Account locked.
+    ChargedBack,
+    /// The chargeback was reversed on appeal, re-crediting the charged back
+    /// amount. The transaction can't be disputed again.
+    Appealed,
+}
+
+impl DisputeState {
+    /// Only open disputes can be resolved or charged back.
+    pub fn is_open(&self) -> bool {
+        matches!(self, DisputeState::Disputed(_))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,53 @@
+use std::collections::{BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use super::{Account, DisputeState, HoldEntry, TransactionEntry, TransferEntry};
+use crate::service::Settings;
+
+/// Represents the complete state of a `TransactionService`, which can be
+/// persisted and later used to resume processing where it left off.
+#[derive(Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    /// All the Accounts in the system.
+    pub account_ledger: HashMap<u16, Account>,
+    /// All the deposits and withdrawals that can still be disputed.
+    pub transaction_ledger: HashMap<u32, TransactionEntry>,
+    /// All manual corrections made by operators.
+    pub adjustment_ledger: HashMap<u32, TransactionEntry>,
+    /// All transfers between Accounts.
+    #[serde(default)]
+    pub transfer_ledger: HashMap<u32, TransferEntry>,
+    /// Interest credited so far per client.
+    #[serde(default)]
+    pub accrued_interest: HashMap<u16, f32>,
+    /// All holds that are still open or were captured.
+    #[serde(default)]
+    pub holds: HashMap<u32, HoldEntry>,
+    /// The dispute state of every transaction that was ever disputed.
+    pub dispute_ledger: HashMap<u32, DisputeState>,
+    /// Overdraft limits configured per client.
+    pub overdraft_limits: HashMap<u16, f32>,
+    /// The deposits and withdrawals of every client, in the order they were made.
+    #[serde(default)]
+    pub client_transactions: HashMap<u16, Vec<u32>>,
+    /// The deposits and withdrawals of all clients, in the order they were made.
+    #[serde(default)]
+    pub history: Vec<u32>,
+    /// Clients whose Accounts are frozen by an operator.
+    #[serde(default)]
+    pub frozen_accounts: BTreeSet<u16>,
+    /// Client and id of the deposits and withdrawals already applied, so that
+    /// an idempotent service keeps ignoring their replays after being restored.
+    #[serde(default)]
+    pub applied_records: Vec<(u16, u32)>,
+    /// Deposits and withdrawals evicted from a capped transaction ledger
+    /// which are still remembered, in the order they were evicted in.
+    #[serde(default)]
+    pub expired_transactions: Vec<u32>,
+    /// The amount moved by the evicted transactions of every client.
+    #[serde(default)]
+    pub expired_totals: HashMap<u16, f32>,
+    /// Behaviours the service was configured with. Snapshots without them
+    /// restore a service with the default behaviours.
+    #[serde(default)]
+    pub(crate) settings: Settings,
+}
\ No newline at end of file
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use super::TransactionType;
+
+/// Throughput metrics gathered while processing transactions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EngineStats {
+    /// Number of transactions processed so far.
+    pub processed: usize,
+    /// Number of transactions that went through successfully.
+    pub succeeded: usize,
+    /// Number of transactions that were declined with an error.
+    pub failed: usize,
+    /// Breakdown of the outcomes for every type of transaction seen.
+    pub by_type: HashMap<TransactionType, TransactionTypeStats>,
+}
+
+/// Outcomes for a single type of transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TransactionTypeStats {
+    /// Number of transactions of this type that went through successfully.
+    pub succeeded: usize,
+    /// Number of transactions of this type that were declined with an error.
+    pub failed: usize,
+}
+
+impl EngineStats {
+    /// Counts the outcome of a single processed transaction.
+    pub(crate) fn record(&mut self, _type: TransactionType, succeeded: bool) {
+        let type_stats = self.by_type.entry(_type).or_default();
+        self.processed += 1;
+        if succeeded {
+            self.succeeded += 1;
+            type_stats.succeeded += 1;
+        } else {
+            self.failed += 1;
+            type_stats.failed += 1;
+        }
+    }
+
+    /// Takes back the count of a transaction which succeeded and was undone since.
+    /// Stats don't survive a restore, so the count never drops below zero.
+    pub(crate) fn unrecord(&mut self, _type: TransactionType) {
+        if let Some(type_stats) = self.by_type.get_mut(&_type).filter(|type_stats| type_stats.succeeded > 0) {
+            type_stats.succeeded -= 1;
+            self.processed -= 1;
+            self.succeeded -= 1;
+        }
+    }
+
+    /// Adds the counts gathered by another service.
+    pub(crate) fn merge(&mut self, other: EngineStats) {
+        self.processed += other.processed;
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        for (_type, other_stats) in other.by_type {
+            let type_stats = self.by_type.entry(_type).or_default();
+            type_stats.succeeded += other_stats.succeeded;
+            type_stats.failed += other_stats.failed;
+        }
+    }
+}
\ No newline at end of file
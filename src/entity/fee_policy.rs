@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// Fee charged on top of every withdrawal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum FeePolicy {
+    /// Withdrawals are free. This is the default.
+    #[default]
+    Free,
+    /// The same fee is charged for every withdrawal.
+    Flat(f32),
+    /// The fee is the given fraction of the amount withdrawn, e.g. `0.01` for 1%.
+    Percentage(f32),
+}
+
+impl FeePolicy {
+    /// Returns the fee for withdrawing `amount`, before any rounding.
+    pub fn fee(&self, amount: f32) -> f32 {
+        match self {
+            FeePolicy::Free => 0.00,
+            FeePolicy::Flat(fee) => *fee,
+            FeePolicy::Percentage(rate) => amount * rate,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+/// Decides how a withdrawal for a client without an Account is handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MissingAccountPolicy {
+    /// Declines the withdrawal with `TransactionError::InvalidAccount`.
+    /// This is the default.
+    #[default]
+    Error,
+    /// Treats the client as if they had an empty Account, so the withdrawal
+    /// fails with `TransactionError::InsufficientFunds` unless the client
+    /// may overdraw, in which case the Account is opened.
+    TreatAsEmpty,
+}
\ No newline at end of file
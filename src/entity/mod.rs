@@ -1,7 +1,35 @@
 mod transaction_type;
 mod transaction_record;
 mod account;
+mod account_filter;
+mod account_report_row;
+mod account_snapshot;
+mod account_status;
+mod audit_event;
+mod balance_point;
+mod dispute_state;
+mod engine_snapshot;
+mod engine_stats;
+mod fee_policy;
+mod missing_account_policy;
+mod reconciliation_mismatch;
+mod rounding_mode;
+mod system_totals;
 
 pub use transaction_type::TransactionType;
-pub use transaction_record::{TransactionRecord, TransactionEntry};
-pub use account::Account;
\ No newline at end of file
+pub use transaction_record::{TransactionRecord, TransactionEntry, TransferEntry, HoldEntry};
+pub use account::Account;
+pub use account_filter::AccountFilter;
+pub use account_report_row::AccountReportRow;
+pub use account_snapshot::AccountSnapshot;
+pub use account_status::AccountStatus;
+pub use audit_event::AuditEvent;
+pub use balance_point::BalancePoint;
+pub use dispute_state::DisputeState;
+pub use engine_snapshot::EngineSnapshot;
+pub use engine_stats::{EngineStats, TransactionTypeStats};
+pub use fee_policy::FeePolicy;
+pub use missing_account_policy::MissingAccountPolicy;
+pub use reconciliation_mismatch::{ReconciliationMismatch, Discrepancy};
+pub use rounding_mode::RoundingMode;
+pub use system_totals::SystemTotals;
\ No newline at end of file
@@ -0,0 +1,18 @@
+/// Accounts whose totals don't match the money the ledgers say
+/// was moved in and out of them, as found by `TransactionService::reconcile`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconciliationMismatch {
+    /// One entry per mismatched Account, sorted by client id.
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Difference between the expected and actual total of one Account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Discrepancy {
+    /// Client whose Account doesn't reconcile.
+    pub client: u16,
+    /// Total recomputed from the ledgers.
+    pub expected: f32,
+    /// Total held by the Account.
+    pub actual: f32,
+}
\ No newline at end of file
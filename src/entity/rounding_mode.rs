@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+/// Strategy used to round money to a fixed number of decimals
+/// when producing the report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundingMode {
+    /// Rounds half away from zero, e.g. `1.00005` becomes `1.0001`.
+    HalfUp,
+    /// Rounds half to even the way Rust formats floats, e.g. `1.00005`
+    /// becomes `1.0000`. This is the default.
+    #[default]
+    HalfEven,
+    /// Drops the extra decimals, e.g. `1.00009` becomes `1.0000`.
+    Truncate,
+}
+
+impl RoundingMode {
+    /// Formats the value with exactly `decimals` decimals.
+    /// `HalfUp` and `Truncate` work on the shortest decimal representation
+    /// of the value so that `1.00005` is treated as written rather than as
+    /// its nearest binary approximation.
+    pub fn format(&self, value: f32, decimals: usize) -> String {
+        if let RoundingMode::HalfEven = self {
+            return format!("{:.*}", decimals, value);
+        }
+        if !value.is_finite() {
+            return format!("{}", value);
+        }
+
+        let repr = format!("{}", value.abs());
+        let (int_part, frac_part) = repr.split_once('.').unwrap_or((&repr, ""));
+        let mut digits: Vec<u8> = int_part.bytes()
+            .chain(frac_part.bytes().chain(std::iter::repeat(b'0')).take(decimals))
+            .map(|d| d - b'0')
+            .collect();
+
+        let round_up = match self {
+            RoundingMode::HalfUp => frac_part.as_bytes().get(decimals).is_some_and(|d| *d >= b'5'),
+            _ => false,
+        };
+        if round_up {
+            Self::increment(&mut digits);
+        }
+
+        let int_len = digits.len() - decimals;
+        let mut formatted = String::new();
+        if value.is_sign_negative() {
+            formatted.push('-');
+        }
+        formatted.extend(digits[..int_len].iter().map(|d| (d + b'0') as char));
+        if decimals > 0 {
+            formatted.push('.');
+            formatted.extend(digits[int_len..].iter().map(|d| (d + b'0') as char));
+        }
+        formatted
+    }
+
+    /// Adds one to the last digit, carrying over to the preceding digits.
+    fn increment(digits: &mut Vec<u8>) {
+        for digit in digits.iter_mut().rev() {
+            if *digit == 9 {
+                *digit = 0;
+            } else {
+                *digit += 1;
+                return;
+            }
+        }
+        digits.insert(0, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_round_half_up() {
+        assert_eq!("1.0001", RoundingMode::HalfUp.format(1.00005, 4));
+        assert_eq!("-1.0001", RoundingMode::HalfUp.format(-1.00005, 4));
+        assert_eq!("10.0000", RoundingMode::HalfUp.format(9.99995, 4));
+    }
+
+    #[test]
+    fn should_round_half_even() {
+        assert_eq!("1.0000", RoundingMode::HalfEven.format(1.00005, 4));
+        assert_eq!(format!("{:.4}", 1.23456_f32), RoundingMode::HalfEven.format(1.23456, 4));
+    }
+
+    #[test]
+    fn should_truncate() {
+        assert_eq!("1.0000", RoundingMode::Truncate.format(1.00005, 4));
+        assert_eq!("0.7000", RoundingMode::Truncate.format(0.7, 4));
+        assert_eq!("1.9999", RoundingMode::Truncate.format(1.99999, 4));
+    }
+}
\ No newline at end of file
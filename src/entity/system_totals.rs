@@ -0,0 +1,13 @@
+/// Sums of the balances across every Account in the system,
+/// used to reconcile that no money was created or destroyed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SystemTotals {
+    /// Sum of the available amount of every Account.
+    pub available: f32,
+    /// Sum of the held amount of every Account.
+    pub held: f32,
+    /// Sum of the total amount of every Account.
+    pub total: f32,
+    /// Number of locked Accounts.
+    pub locked_accounts: usize,
+}
\ No newline at end of file
@@ -1,8 +1,8 @@
 use super::TransactionType;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// Represents the transaction for different clients.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct TransactionRecord {
     /// Represents the type of Transaction.
     #[serde(alias = "type")]
@@ -13,17 +13,103 @@ pub struct TransactionRecord {
     pub tx: u32,
     /// Amount pertaining to the transaction.
     /// It is only populated for `TransactionType::DEPOSIT`
-    /// and `TransactionType::WITHDRAWAL`.
+    /// and `TransactionType::WITHDRAWAL`, or for `TransactionType::DISPUTE`
+    /// when only part of the transaction is disputed.
+    /// Parsed from its text as a plain decimal number, see `deserialize_amount`.
+    #[serde(default, deserialize_with = "deserialize_amount")]
     pub amount: Option<f32>,
+    /// Unique id representing the client receiving the funds.
+    /// It is only populated for `TransactionType::TRANSFER`.
+    #[serde(rename = "target")]
+    pub target_client: Option<u16>,
+    /// Time the transaction was made at, in unix milliseconds.
+    /// The column is optional in the input.
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+    /// Currency code of the amount, such as `USD`.
+    /// The column is optional in the input.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Free-form reference to the transaction in an external system.
+    /// It is carried through to the ledgers and the audit log, but
+    /// never changes any balance. The column is optional in the input.
+    #[serde(default)]
+    pub reference: Option<String>,
+}
+
+/// Parses an amount from its text, only accepting plain decimal numbers
+/// such as `-12.5`, `3` or `.25`. Exponents like `1e3`, `inf` and any
+/// other junk are rejected, since they are almost always a data error.
+/// The text is taken as is, so formats which infer numbers from text
+/// can't turn `1e3` into `1000` before it is checked.
+fn deserialize_amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    let text = match Option::<String>::deserialize(deserializer)? {
+        Some(text) => text,
+        None => return Ok(None),
+    };
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(&text);
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let plain = !(whole.is_empty() && fraction.is_empty())
+        && whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit());
+    match text.parse::<f32>() {
+        Ok(amount) if plain => Ok(Some(amount)),
+        _ => Err(serde::de::Error::custom(format!("invalid amount `{}`, expected a plain decimal number", text))),
+    }
 }
 
 /// Represents the entry used to keep track of transactions for
 /// disputes and other transactions.
 /// Internally we keep track of transactions where each tx
 /// maps to a TransactionEntry.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TransactionEntry {
     /// Unique id representing the client.
     pub client: u16,
     /// Amount pertaining to the transaction.
     pub amount: f32,
-}
\ No newline at end of file
+    /// Part of the amount that has already been charged back,
+    /// which can't be disputed again.
+    #[serde(default)]
+    pub charged_back: f32,
+    /// Part of a withdrawal that has been refunded to the client.
+    #[serde(default)]
+    pub refunded: f32,
+    /// Time the transaction was made at, in unix milliseconds, if known.
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+    /// Reference given with the transaction, if any.
+    #[serde(default)]
+    pub reference: Option<String>,
+}
+
+impl TransactionEntry {
+    /// Withdrawals are recorded with a negative amount. A withdrawal of
+    /// zero is recorded as `-0.0`, so the sign is checked rather than the value.
+    pub fn is_withdrawal(&self) -> bool {
+        self.amount.is_sign_negative()
+    }
+}
+
+/// Represents a transfer that moved `amount` from `client`
+/// to the Account of `target`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TransferEntry {
+    /// Client the money was taken from.
+    pub client: u16,
+    /// Client the money was moved to.
+    pub target: u16,
+    /// Amount that was moved.
+    pub amount: f32,
+}
+
+/// Represents a hold that reserves `amount` of the available funds
+/// of `client`, such as a card authorization.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HoldEntry {
+    /// Client whose funds are held.
+    pub client: u16,
+    /// Amount that is held.
+    pub amount: f32,
+    /// Whether the held amount was captured, i.e. taken from the Account.
+    pub captured: bool,
+}
@@ -1,9 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// An enum to represent the different types of
 /// possible transactions in the system.
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum TransactionType {
     /// Adds money to the existing Account or
     /// creates a new account with the amount specified.
@@ -18,5 +18,111 @@ pub enum TransactionType {
     RESOLVE,
     /// Reverses the transaction under dispute and locks the account
     /// for further transactions.
-    CHARGEBACK
-}
\ No newline at end of file
+    CHARGEBACK,
+    /// Reverses the transaction under dispute like a chargeback, but
+    /// keeps the account active while the client appeals.
+    #[allow(non_camel_case_types)]
+    SOFT_CHARGEBACK,
+    /// Moves the amount specified from the client's Account
+    /// to the Account of the target client.
+    TRANSFER,
+    /// Reinstates an Account that was previously locked
+    /// by a chargeback.
+    UNLOCK,
+    /// Removes an Account with zero balance from the system.
+    CLOSE,
+    /// Manual correction by an operator which credits or debits
+    /// the Account by a signed amount.
+    ADJUSTMENT,
+    /// Moves a resolved dispute back into dispute and holds
+    /// the previously disputed amount again.
+    REOPEN,
+    /// Reverses a chargeback after a successful appeal, re-crediting
+    /// the charged back amount and unlocking the Account.
+    APPEAL,
+    /// Returns money of an earlier withdrawal to the client, e.g. when a
+    /// merchant refunds a payment. Unlike a chargeback nothing is held and
+    /// the Account stays unlocked.
+    REFUND,
+    /// A type this version doesn't know, e.g. from a newer producer.
+    /// It is kept so that the row is rejected on its own when processed,
+    /// instead of failing the whole input.
+    Unknown(String),
+}
+
+/// Always written as its lowercase name, or the name as it was read
+/// for unknown types, so that every format reads it back the same way.
+impl Serialize for TransactionType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let name = match self {
+            TransactionType::DEPOSIT => "deposit",
+            TransactionType::WITHDRAWAL => "withdrawal",
+            TransactionType::DISPUTE => "dispute",
+            TransactionType::RESOLVE => "resolve",
+            TransactionType::CHARGEBACK => "chargeback",
+            TransactionType::SOFT_CHARGEBACK => "soft_chargeback",
+            TransactionType::TRANSFER => "transfer",
+            TransactionType::UNLOCK => "unlock",
+            TransactionType::CLOSE => "close",
+            TransactionType::ADJUSTMENT => "adjustment",
+            TransactionType::REOPEN => "reopen",
+            TransactionType::APPEAL => "appeal",
+            TransactionType::REFUND => "refund",
+            TransactionType::Unknown(name) => name,
+        };
+        serializer.serialize_str(name)
+    }
+}
+
+/// Accepts the type names in any casing, since input files
+/// are not consistent about it. Names that aren't known become `Unknown`.
+impl<'de> Deserialize<'de> for TransactionType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match name.to_ascii_lowercase().as_str() {
+            "deposit" => Ok(TransactionType::DEPOSIT),
+            "withdrawal" => Ok(TransactionType::WITHDRAWAL),
+            "dispute" => Ok(TransactionType::DISPUTE),
+            "resolve" => Ok(TransactionType::RESOLVE),
+            "chargeback" => Ok(TransactionType::CHARGEBACK),
+            "soft_chargeback" => Ok(TransactionType::SOFT_CHARGEBACK),
+            "transfer" => Ok(TransactionType::TRANSFER),
+            "unlock" => Ok(TransactionType::UNLOCK),
+            "close" => Ok(TransactionType::CLOSE),
+            "adjustment" => Ok(TransactionType::ADJUSTMENT),
+            "reopen" => Ok(TransactionType::REOPEN),
+            "appeal" => Ok(TransactionType::APPEAL),
+            "refund" => Ok(TransactionType::REFUND),
+            _ => Ok(TransactionType::Unknown(name)),
+        }
+    }
+}
+
+impl TransactionType {
+    /// Financial operations move money around and are therefore
+    /// not allowed on a locked Account. An appeal is how an Account
+    /// locked by a chargeback is reinstated, so it is allowed as well.
+    pub fn is_financial(&self) -> bool {
+        !matches!(self, TransactionType::UNLOCK | TransactionType::APPEAL)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::TransactionType;
+
+    #[test]
+    fn should_deserialize_types_in_any_casing() {
+        for name in ["\"deposit\"", "\"Deposit\"", "\"DEPOSIT\""] {
+            let parsed: TransactionType = serde_json::from_str(name).unwrap();
+            assert_eq!(TransactionType::DEPOSIT, parsed);
+        }
+    }
+
+    #[test]
+    fn should_keep_unknown_types() {
+        let parsed: TransactionType = serde_json::from_str("\"Rebate\"").unwrap();
+
+        assert_eq!(TransactionType::Unknown("Rebate".to_string()), parsed);
+        assert_eq!("\"Rebate\"", serde_json::to_string(&parsed).unwrap());
+    }
+}
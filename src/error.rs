@@ -1,8 +1,9 @@
+use std::io;
 use thiserror::Error;
 
 /// Encompasses the possible errors
 /// that are possible while executing transactions.
-#[derive(Error, PartialEq, Debug)]
+#[derive(Error, Clone, PartialEq, Debug)]
 pub enum TransactionError {
     /// Error for when there is a dispute request for a transaction that
     /// is already under dispute.
@@ -20,8 +21,13 @@ pub enum TransactionError {
     InvalidOperation,
     /// Occurs during transactions where the client
     /// has not yet opened an account.
-    #[error("Given clientId does not have an account.")]
-    InvalidAccount,
+    #[error("Given clientId {client} does not have an account, declining transaction {tx}.")]
+    InvalidAccount {
+        /// Client that the transaction was made for.
+        client: u16,
+        /// Transaction that was declined.
+        tx: u32,
+    },
     /// Occurs during Deposit/ Withdrawal if `TransactionRecord`
     /// does not have the amount specified.
     #[error("Give transaction record does not have the amount specified.")]
@@ -30,14 +36,192 @@ pub enum TransactionError {
     /// dispute/resolve/chargeback is non-existent.
     #[error("Given transaction does not exist.")]
     MissingTransaction,
+    /// Occurs during the Dispute flow where the transaction exists
+    /// but belongs to a different client.
+    #[error("Given transaction belongs to a different client.")]
+    TransactionClientMismatch,
     /// Error for when a transaction is tried on a locked account.
     #[error("Given account is locked, due to which the transaction has been declined.")]
     LockedAccount,
+    /// Error for when a transaction is tried on an account that an
+    /// operator has frozen.
+    #[error("Given account is frozen, due to which the transaction has been declined.")]
+    FrozenAccount,
+    /// Occurs when disputing a withdrawal while only deposits may be disputed.
+    #[error("Given transaction is a withdrawal, which can't be disputed.")]
+    CannotDisputeWithdrawal,
     /// Error for when resolve/chargeback is attempted for a transaction which
     /// is not disputed yet.
     #[error("Given transaction is not currently under dispute.")]
     TransactionNotDisputed,
+    /// Occurs during a Transfer if `TransactionRecord`
+    /// does not have the target client specified.
+    #[error("Given transaction record does not have the target client specified.")]
+    MissingTargetClient,
+    /// Occurs during a Transfer whose target client is the client itself,
+    /// which usually points at a routing error in the input.
+    #[error("Given transfer has the same source and target client.")]
+    SelfTransfer,
+    /// Occurs when a Deposit, Withdrawal, Refund, Transfer, partial Dispute
+    /// or hold carries an amount of zero or below. Only Adjustments are signed.
+    #[error("Given amount has to be greater than zero.")]
+    NonPositiveAmount,
+    /// Occurs during Resolve/ Chargeback if the Account holds less
+    /// than the amount under dispute.
+    #[error("Given account does not hold enough funds to settle the dispute.")]
+    InsufficientHeldFunds,
+    /// Occurs when closing an Account that still has funds in it.
+    #[error("Given account still has a non-zero balance.")]
+    NonZeroBalance,
+    /// Occurs when an Adjustment would leave the Account with
+    /// a negative total.
+    #[error("Given adjustment would drive the account total below zero.")]
+    NegativeTotal,
+    /// Occurs when a partial dispute is raised for more than the amount
+    /// of the transaction that can still be disputed.
+    #[error("Given dispute amount exceeds the disputable amount of the transaction.")]
+    DisputeExceedsTransaction,
+    /// Occurs when a refund is for more than what is left to refund
+    /// of the withdrawal, or for a negative amount.
+    #[error("Given refund amount exceeds the refundable amount of the withdrawal.")]
+    RefundExceedsTransaction,
+    /// Occurs when a refund references a transaction which is not a withdrawal.
+    #[error("Only withdrawals can be refunded.")]
+    TransactionNotRefundable,
+    /// Occurs when a dispute is raised for a client that already has
+    /// the maximum number of open disputes.
+    #[error("Given client already has the maximum number of open disputes.")]
+    TooManyOpenDisputes,
+    /// Occurs when a new Account would exceed the configured number of Accounts.
+    #[error("Given clientId {0} can not open an account, the account limit is reached.")]
+    AccountLimitReached(u16),
+    /// Occurs when a single withdrawal is larger than the configured limit.
+    #[error("Given withdrawal exceeds the maximum allowed amount.")]
+    WithdrawalLimitExceeded,
+    /// Occurs when the currency of a record doesn't match the currency
+    /// of the Accounts it changes.
+    #[error("Given currency does not match the currency of the account.")]
+    CurrencyMismatch,
+    /// Occurs when an amount is not finite, or is so large that the
+    /// balances it changes could no longer be represented.
+    #[error("Given amount is out of the range that can be represented.")]
+    AmountOutOfRange,
+    /// Occurs when interest is accrued at a negative rate.
+    #[error("Given interest rate has to be zero or above.")]
+    InvalidRate,
+    /// Occurs when an amount has more decimals than the configured scale,
+    /// or than `ReaderOptions::max_decimal_places` while parsing without a service.
+    #[error("Given amount has more decimals than the configured scale allows.")]
+    AmountExceedsScale,
+    /// Occurs when a Resolve or Chargeback record carries an amount.
+    /// Disputes may carry an amount to raise a partial dispute.
+    #[error("Given record should not carry an amount.")]
+    UnexpectedAmount,
+    /// Occurs when disputing, resolving or charging back a transaction
+    /// which was already charged back in full.
+    #[error("Given transaction was charged back and is final.")]
+    TransactionFinalized,
+    /// Occurs when a record references a transaction which was evicted
+    /// from a capped transaction ledger.
+    #[error("Given transaction has expired and can no longer be referenced.")]
+    TransactionExpired,
+    /// Occurs when appealing a transaction which is not charged back.
+    #[error("Given transaction does not have a chargeback to appeal.")]
+    TransactionNotChargedBack,
+    /// Occurs when reopening a dispute that was never resolved.
+    #[error("Given transaction does not have a resolved dispute to reopen.")]
+    TransactionNotResolved,
+    /// Occurs when an operation outside of a transaction targets a client
+    /// that does not have an account.
+    #[error("Given clientId {0} does not have an account.")]
+    AccountNotFound(u16),
+    /// Occurs when placing a hold with the id of an existing hold.
+    #[error("Given hold already exists.")]
+    HoldAlreadyExists,
+    /// Occurs when releasing or capturing a hold which is not open.
+    #[error("Given hold does not exist or is no longer open.")]
+    MissingHold,
+    /// Occurs when opening an account for a client that already has one.
+    #[error("Given clientId {0} already has an account.")]
+    AccountAlreadyExists(u16),
+    /// Occurs when processing a record whose type this version doesn't know.
+    #[error("Given transaction type {0} is not supported.")]
+    UnsupportedTransactionType(String),
+    /// Occurs when merging services which both know of the same transaction.
+    #[error("Given transaction {0} already exists.")]
+    DuplicateTransaction(u32),
+    /// Occurs when merging services which give the same client different overdraft limits.
+    #[error("Given clientId {0} has a different overdraft limit in each service.")]
+    ConflictingOverdraftLimit(u16),
+    /// Occurs when an Account's total is not the sum of its
+    /// available and held amounts.
+    #[error("Given clientId {0} has a total which does not match its available and held amounts.")]
+    UnbalancedAccount(u16),
+    /// Occurs when the input has a client id outside of the range of a `u16`.
+    #[error("Given client id {0} is out of range, client ids go from 0 to 65535.")]
+    ClientIdOutOfRange(String),
+    /// Occurs when the input can't be parsed into transactions.
+    /// The underlying error is kept as a message so that errors can
+    /// still be cloned and compared.
+    #[error("Given input could not be parsed: {0}")]
+    Parse(String),
+    /// Occurs when the input or output can't be read or written.
+    #[error("Given input could not be read: {0}")]
+    Io(String),
 }
 
 /// Simplified Result type which uses TransactionError.
-pub type Result<T> = std::result::Result<T, TransactionError>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, TransactionError>;
+
+impl From<io::Error> for TransactionError {
+    fn from(err: io::Error) -> Self {
+        TransactionError::Io(err.to_string())
+    }
+}
+
+impl From<csv::Error> for TransactionError {
+    fn from(err: csv::Error) -> Self {
+        match err.kind() {
+            csv::ErrorKind::Io(io_err) => TransactionError::Io(io_err.to_string()),
+            _ => TransactionError::Parse(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for TransactionError {
+    fn from(err: serde_json::Error) -> Self {
+        match err.classify() {
+            serde_json::error::Category::Io => TransactionError::Io(err.to_string()),
+            _ => TransactionError::Parse(err.to_string()),
+        }
+    }
+}
+
+/// Bincode is only used on bytes in memory, where an I/O error
+/// means that the input was cut short.
+impl From<bincode::Error> for TransactionError {
+    fn from(err: bincode::Error) -> Self {
+        TransactionError::Parse(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_clone_an_error() {
+        let error = TransactionError::InvalidAccount { client: 1, tx: 2 };
+        let cloned = error.clone();
+
+        assert_eq!(error, cloned);
+        assert_eq!(error.to_string(), cloned.to_string());
+    }
+
+    #[test]
+    fn should_convert_io_errors() {
+        let err = io::Error::new(io::ErrorKind::NotFound, "missing.csv");
+
+        assert_eq!(TransactionError::Io("missing.csv".to_string()), TransactionError::from(err));
+    }
+}
\ No newline at end of file
@@ -6,11 +6,13 @@ mod error;
 mod entity;
 mod service;
 mod traits;
+mod reader;
 
 pub use error::{TransactionError, Result};
-pub use entity::{TransactionType, TransactionRecord, TransactionEntry, Account};
-pub use service::TransactionService;
-pub use traits::Transaction;
+pub use entity::{TransactionType, TransactionRecord, TransactionEntry, TransferEntry, HoldEntry, Account, AccountFilter, AccountReportRow, AccountSnapshot, AccountStatus, AuditEvent, BalancePoint, DisputeState, EngineSnapshot, EngineStats, TransactionTypeStats, RoundingMode, SystemTotals, ReconciliationMismatch, Discrepancy, FeePolicy, MissingAccountPolicy};
+pub use service::{TransactionService, TransactionServiceBuilder};
+pub use traits::{Transaction, AccountStore};
+pub use reader::{process_reader, process_reader_with, process_files, process_files_with, process_json, process_json_with, records_from_reader, records_from_reader_with, run_csv, ReaderOptions};
 
 
 
@@ -1,24 +1,104 @@
-use std::{env, process};
-use std::error::Error;
-use transactions_engine::{TransactionRecord, TransactionService};
+use std::{env, fmt, process};
+use std::fs::File;
+use std::io::{self, Write};
+use transactions_engine::{process_files_with, ReaderOptions, Result, TransactionService};
 
 #[macro_use]
 extern crate log;
 
 
-fn process_file(path : String, mut service: TransactionService) -> Result<(), Box<dyn Error>> {
-    // Build the CSV reader and iterate over each record.
-    let mut rdr = csv::Reader::from_path(path)?;
-    for result in rdr.deserialize() {
-        let record : TransactionRecord = result?;
-        debug!("{:?}", record);
-        let res = service.process(record);
-        match res {
-            Ok(_) => info!("Transaction went through successfully"),
-            Err(err) => error!("Error while executing transaction: {:?}", err)
+const USAGE: &str = "usage: transactions_engine [--output <file>] [--format csv|json] [--fail-fast] [--progress <records>] <file>...";
+
+/// Formats the report can be written in.
+#[derive(Debug, PartialEq)]
+enum ReportFormat {
+    Csv,
+    Json,
+}
+
+/// Configuration given on the command line. Every argument which is not
+/// an option is a file to read transactions from.
+#[derive(Debug, PartialEq)]
+struct Config {
+    paths: Vec<String>,
+    output: Option<String>,
+    format: ReportFormat,
+    fail_fast: bool,
+    progress: Option<usize>,
+}
+
+/// Reasons the command line can't be turned into a `Config`.
+#[derive(Debug, PartialEq)]
+enum UsageError {
+    /// No file to read transactions from was given.
+    MissingInput,
+    /// The option was given without its value.
+    MissingValue(&'static str),
+    /// The report format is not one of the supported ones.
+    UnknownFormat(String),
+    /// The progress interval is not a positive number of records.
+    InvalidInterval(String),
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsageError::MissingInput => write!(f, "no input file given"),
+            UsageError::MissingValue(option) => write!(f, "{} expects a value", option),
+            UsageError::UnknownFormat(format) => write!(f, "unknown report format {}, expected csv or json", format),
+            UsageError::InvalidInterval(interval) => write!(f, "invalid progress interval {}, expected a number of records", interval),
         }
     }
-    service.generate_report();
+}
+
+/// Parses the arguments following the program name.
+fn parse_args(args: &[String]) -> std::result::Result<Config, UsageError> {
+    let mut config = Config { paths: Vec::new(), output: None, format: ReportFormat::Csv, fail_fast: false, progress: None };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => match args.next() {
+                Some(path) => config.output = Some(path.clone()),
+                None => return Err(UsageError::MissingValue("--output")),
+            },
+            "--format" => match args.next().map(String::as_str) {
+                Some("csv") => config.format = ReportFormat::Csv,
+                Some("json") => config.format = ReportFormat::Json,
+                Some(format) => return Err(UsageError::UnknownFormat(format.to_string())),
+                None => return Err(UsageError::MissingValue("--format")),
+            },
+            "--fail-fast" => config.fail_fast = true,
+            "--progress" => match args.next() {
+                Some(interval) => match interval.parse::<usize>() {
+                    Ok(records) if records > 0 => config.progress = Some(records),
+                    _ => return Err(UsageError::InvalidInterval(interval.clone())),
+                },
+                None => return Err(UsageError::MissingValue("--progress")),
+            },
+            _ => config.paths.push(arg.clone()),
+        }
+    }
+    if config.paths.is_empty() {
+        return Err(UsageError::MissingInput);
+    }
+    Ok(config)
+}
+
+
+fn process_file(config: &Config, mut service: TransactionService) -> Result<()> {
+    let mut options = ReaderOptions::new().fail_fast(config.fail_fast);
+    if let Some(interval) = config.progress {
+        options = options.progress(interval, |processed| eprintln!("processed {} records", processed));
+    }
+    process_files_with(&config.paths, &mut service, &options)?;
+    let writer: Box<dyn Write> = match &config.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    match config.format {
+        ReportFormat::Csv => service.write_report(writer)?,
+        ReportFormat::Json => service.write_json_report(writer)?,
+    }
     Ok(())
 }
 
@@ -26,12 +106,47 @@ fn process_file(path : String, mut service: TransactionService) -> Result<(), Bo
 fn main() {
     env_logger::init();
     info!("Starting up!");
-    let args: Vec<String> = env::args().collect();
-    let file_name = args[1].clone();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = match parse_args(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
     let service : TransactionService = Default::default();
-    if let Err(err) = process_file(file_name, service) {
+    if let Err(err) = process_file(&config, service) {
         // this path occurs if there any errors while parsing the csv.
         warn!("error running example: {}", err);
         process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn should_require_an_input_file() {
+        assert_eq!(Err(UsageError::MissingInput), parse_args(&[]));
+        assert_eq!(Err(UsageError::MissingInput), parse_args(&args(&["--fail-fast"])));
+    }
+
+    #[test]
+    fn should_parse_the_input_files_and_options() {
+        assert_eq!(Ok(Config {
+            paths: args(&["monday.csv", "tuesday.csv"]),
+            output: Some("report.json".to_string()),
+            format: ReportFormat::Json,
+            fail_fast: false,
+            progress: None,
+        }), parse_args(&args(&["monday.csv", "--output", "report.json", "--format", "json", "tuesday.csv"])));
+        assert_eq!(Err(UsageError::MissingValue("--output")), parse_args(&args(&["input.csv", "--output"])));
+        assert_eq!(Some(1000), parse_args(&args(&["input.csv", "--progress", "1000"])).unwrap().progress);
+        assert_eq!(Err(UsageError::InvalidInterval("0".to_string())), parse_args(&args(&["input.csv", "--progress", "0"])));
+    }
+}
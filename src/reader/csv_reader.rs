@@ -0,0 +1,97 @@
+use std::io::Read;
+use log::{debug, info, error, Level};
+use crate::{ReaderOptions, Result, TransactionError, TransactionRecord, TransactionService};
+use crate::service::validate_decimals;
+
+/// Parses CSV transactions from any `Read` source, such as a file, stdin
+/// or an in-memory buffer, and processes them in order with the given service.
+/// Whitespace around headers and fields is trimmed since input files are often hand-edited.
+/// Failed transactions are logged and skipped, whereas malformed CSV stops the processing
+/// with a `TransactionError::Parse`.
+pub fn process_reader<R: Read>(reader: R, service: &mut TransactionService) -> Result<()> {
+    process_reader_with(reader, service, &ReaderOptions::default())
+}
+
+/// Processes CSV transactions like `process_reader`, with the given options.
+pub fn process_reader_with<R: Read>(reader: R, service: &mut TransactionService, options: &ReaderOptions) -> Result<()> {
+    process_counted(reader, service, options, &mut 0)
+}
+
+/// Processes the records like `process_reader_with`, counting them on top
+/// of `processed` so that the progress can span several readers.
+pub(super) fn process_counted<R: Read>(reader: R, service: &mut TransactionService, options: &ReaderOptions, processed: &mut usize) -> Result<()> {
+    let options = &options.for_scale(service.scale());
+    for result in records_from_reader_with(reader, options) {
+        *processed += 1;
+        options.report_progress(*processed);
+        let record = result?;
+        if service.logs(Level::Debug) {
+            debug!("{:?}", record);
+        }
+        match service.process(record) {
+            Ok(_) if service.logs(Level::Info) => info!("Transaction went through successfully"),
+            Err(err) if options.fail_fast => return Err(err),
+            Err(err) if service.logs(Level::Error) => error!("Error while executing transaction: {:?}", err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Lazily parses CSV transactions from any `Read` source, one record at a time,
+/// so that callers can decide how and when each record is processed.
+/// Whitespace is trimmed the same way as in `process_reader`.
+pub fn records_from_reader<R: Read>(reader: R) -> impl Iterator<Item = Result<TransactionRecord>> {
+    records_from_reader_with(reader, &ReaderOptions::default())
+}
+
+/// Lazily parses CSV transactions like `records_from_reader`, with the given options.
+pub fn records_from_reader_with<R: Read>(reader: R, options: &ReaderOptions) -> impl Iterator<Item = Result<TransactionRecord>> {
+    let max_decimal_places = options.max_decimal_places;
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .delimiter(options.delimiter)
+        .from_reader(reader);
+    let (headers, header_error) = match reader.headers() {
+        Ok(headers) => (headers.clone(), None),
+        Err(err) => (csv::StringRecord::new(), Some(err)),
+    };
+    let client = headers.iter().position(|name| name == "client");
+    let amount = headers.iter().position(|name| name == "amount");
+    let rows = reader.into_records().take(if header_error.is_some() { 0 } else { usize::MAX });
+
+    header_error.map(|err| Err(err.into())).into_iter()
+        .chain(rows.map(move |row| {
+            let row = row?;
+            if let Some(client) = client.and_then(|index| row.get(index)) {
+                validate_client_id(client)?;
+            }
+            if let Some(amount) = amount.and_then(|index| row.get(index)) {
+                validate_decimals(amount, max_decimal_places)?;
+            }
+            Ok(row.deserialize(Some(&headers))?)
+        }))
+}
+
+/// Checks a client id before it is parsed, since serde only reports that
+/// an integer didn't fit without naming the offending value.
+pub(super) fn validate_client_id(text: &str) -> Result<()> {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let integer = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+    if integer && text.parse::<u16>().is_err() {
+        return Err(TransactionError::ClientIdOutOfRange(text.to_string()));
+    }
+    Ok(())
+}
+
+/// Processes the CSV transactions in `input` with a fresh service and returns
+/// the resulting report as CSV. Nothing touches the filesystem, which makes it
+/// suitable for environments such as WebAssembly.
+pub fn run_csv(input: &str) -> Result<String> {
+    let mut service: TransactionService = Default::default();
+    process_reader(input.as_bytes(), &mut service)?;
+
+    let mut report = Vec::new();
+    service.write_report(&mut report)?;
+    String::from_utf8(report).map_err(|err| TransactionError::Io(err.to_string()))
+}
\ No newline at end of file
@@ -0,0 +1,29 @@
+use std::fs::File;
+use std::io;
+use flate2::read::GzDecoder;
+use crate::{ReaderOptions, Result, TransactionService};
+use super::csv_reader::process_counted;
+
+/// Processes the given CSV files one after the other with the same service,
+/// so that several batches can be merged into a single run. Each file has its
+/// own header row. A path of `-` reads from stdin and paths ending in `.gz`
+/// are decompressed on the fly. Processing stops at the first file that can't be read.
+pub fn process_files(paths: &[String], service: &mut TransactionService) -> Result<()> {
+    process_files_with(paths, service, &ReaderOptions::default())
+}
+
+/// Processes the given CSV files like `process_files`, with the given options.
+/// With `ReaderOptions::fail_fast` the remaining files are skipped as well.
+pub fn process_files_with(paths: &[String], service: &mut TransactionService, options: &ReaderOptions) -> Result<()> {
+    let mut processed = 0;
+    for path in paths {
+        if path == "-" {
+            process_counted(io::stdin().lock(), service, options, &mut processed)?;
+        } else if path.ends_with(".gz") {
+            process_counted(GzDecoder::new(File::open(path)?), service, options, &mut processed)?;
+        } else {
+            process_counted(File::open(path)?, service, options, &mut processed)?;
+        }
+    }
+    Ok(())
+}
\ No newline at end of file
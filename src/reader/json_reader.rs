@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+use log::{debug, info, error, Level};
+use crate::{ReaderOptions, Result, TransactionRecord, TransactionService};
+use crate::service::validate_decimals;
+use super::csv_reader::validate_client_id;
+
+/// Parses a JSON array of transactions from any `Read` source and processes
+/// them in order with the given service. Records use the same field names as
+/// the CSV columns, e.g. `{"type": "deposit", "client": 1, "tx": 1, "amount": 1.0}`.
+/// Failed transactions are logged and skipped, whereas malformed JSON stops the processing
+/// before any transaction is processed.
+pub fn process_json<R: Read>(reader: R, service: &mut TransactionService) -> Result<()> {
+    process_json_with(reader, service, &ReaderOptions::default())
+}
+
+/// Processes JSON transactions like `process_json`, with the given options.
+/// The delimiter only applies to CSV and is ignored, and amounts may carry
+/// as many decimals as the scale of the service.
+pub fn process_json_with<R: Read>(reader: R, service: &mut TransactionService, options: &ReaderOptions) -> Result<()> {
+    let scale = service.scale();
+    let objects: Vec<BTreeMap<String, Box<RawValue>>> = serde_json::from_reader(reader)?;
+    let records = objects.into_iter()
+        .map(|fields| {
+            let value = Value::Object(fields.iter()
+                .map(|(name, raw)| Ok((name.clone(), field_value(name, raw)?)))
+                .collect::<Result<Map<String, Value>>>()?);
+            if let Some(Value::String(amount)) = value.get("amount") {
+                validate_decimals(amount, scale)?;
+            }
+            if let Some(client) = value.get("client").filter(|client| client.is_number()) {
+                validate_client_id(&client.to_string())?;
+            }
+            Ok(TransactionRecord::deserialize(value)?)
+        })
+        .collect::<Result<Vec<TransactionRecord>>>()?;
+    for (processed, record) in records.into_iter().enumerate() {
+        options.report_progress(processed + 1);
+        if service.logs(Level::Debug) {
+            debug!("{:?}", record);
+        }
+        match service.process(record) {
+            Ok(_) if service.logs(Level::Info) => info!("Transaction went through successfully"),
+            Err(err) if options.fail_fast => return Err(err),
+            Err(err) if service.logs(Level::Error) => error!("Error while executing transaction: {:?}", err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parses a field of a record. Numeric amounts are kept as the text they were
+/// written with, since parsing them as JSON numbers would turn `1e3` into `1000.0`
+/// before the amount is checked for exponents.
+fn field_value(name: &str, raw: &RawValue) -> Result<Value> {
+    let text = raw.get().trim();
+    if name == "amount" && text.starts_with(|c: char| c == '-' || c.is_ascii_digit()) {
+        return Ok(Value::String(text.to_string()));
+    }
+    Ok(serde_json::from_str(text)?)
+}
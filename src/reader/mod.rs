@@ -0,0 +1,9 @@
+mod csv_reader;
+mod file_reader;
+mod json_reader;
+mod reader_options;
+
+pub use csv_reader::{process_reader, process_reader_with, records_from_reader, records_from_reader_with, run_csv};
+pub use file_reader::{process_files, process_files_with};
+pub use json_reader::{process_json, process_json_with};
+pub use reader_options::ReaderOptions;
\ No newline at end of file
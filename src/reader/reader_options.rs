@@ -0,0 +1,94 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+type ProgressCallback = Arc<Mutex<dyn FnMut(usize) + Send>>;
+
+/// Decimal places allowed when there is no service to take the scale from,
+/// which matches the default scale of `TransactionService`.
+const DEFAULT_DECIMAL_PLACES: u32 = 4;
+
+/// Configures how `process_reader_with` and `process_files_with`
+/// handle the records they read. The defaults match `process_reader`.
+#[derive(Clone)]
+pub struct ReaderOptions {
+    pub(crate) fail_fast: bool,
+    pub(crate) max_decimal_places: u32,
+    pub(crate) delimiter: u8,
+    pub(crate) progress: Option<(usize, ProgressCallback)>,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        ReaderOptions {
+            fail_fast: false,
+            max_decimal_places: DEFAULT_DECIMAL_PLACES,
+            delimiter: b',',
+            progress: None,
+        }
+    }
+}
+
+impl fmt::Debug for ReaderOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReaderOptions")
+            .field("fail_fast", &self.fail_fast)
+            .field("max_decimal_places", &self.max_decimal_places)
+            .field("delimiter", &(self.delimiter as char))
+            .field("progress_interval", &self.progress.as_ref().map(|(interval, _)| interval))
+            .finish()
+    }
+}
+
+impl ReaderOptions {
+    /// Starts with every option set to its default.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Stops at the first transaction that fails and returns its error,
+    /// instead of logging it and carrying on with the next record.
+    /// Records before the failing one stay applied.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Rejects amounts with more decimal places than `max_decimal_places`
+    /// while parsing with `records_from_reader_with`, which has no service to
+    /// take the scale from. Defaults to four, the default scale. The functions
+    /// processing the records always allow as many as the scale of their service.
+    pub fn max_decimal_places(mut self, max_decimal_places: u32) -> Self {
+        self.max_decimal_places = max_decimal_places;
+        self
+    }
+
+    /// Returns the options with the decimal places following the given scale.
+    pub(crate) fn for_scale(&self, scale: u32) -> Self {
+        ReaderOptions { max_decimal_places: scale, ..self.clone() }
+    }
+
+    /// Reads CSV input separated by `delimiter` instead of commas, e.g.
+    /// `b';'` for semicolon exports or `b'\t'` for TSV files.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Calls `callback` with the number of records read so far after every
+    /// `interval` records, e.g. to drive a progress bar. With `process_files_with`
+    /// the count carries on from one file to the next.
+    pub fn progress<F: FnMut(usize) + Send + 'static>(mut self, interval: usize, callback: F) -> Self {
+        self.progress = Some((interval.max(1), Arc::new(Mutex::new(callback))));
+        self
+    }
+
+    /// Reports the progress if `processed` is a multiple of the interval.
+    pub(crate) fn report_progress(&self, processed: usize) {
+        if let Some((interval, callback)) = &self.progress {
+            if processed.is_multiple_of(*interval) {
+                let mut callback = callback.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                callback(processed);
+            }
+        }
+    }
+}
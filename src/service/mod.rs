@@ -1,3 +1,8 @@
+mod settings;
 mod transaction_service;
+mod transaction_service_builder;
 
-pub use transaction_service::TransactionService;
\ No newline at end of file
+pub(crate) use settings::Settings;
+pub use transaction_service::TransactionService;
+pub(crate) use transaction_service::validate_decimals;
+pub use transaction_service_builder::TransactionServiceBuilder;
\ No newline at end of file
@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize, Serializer};
+use crate::{FeePolicy, MissingAccountPolicy, RoundingMode, TransactionType};
+
+/// Optional behaviours of the `TransactionService`. The defaults
+/// match the behaviour of a service without any configuration.
+/// They are part of the `EngineSnapshot`, so that a restored service
+/// behaves like the one it was captured from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    /// Rounding applied to money in the report.
+    pub(crate) rounding_mode: RoundingMode,
+    /// Number of decimals money is reported with and amounts may carry.
+    pub(crate) scale: u32,
+    /// Tolerance used when checking whether funds cover an amount.
+    pub(crate) epsilon: f32,
+    /// Largest amount allowed in a single withdrawal, if any.
+    pub(crate) max_withdrawal: Option<f32>,
+    /// Fee charged on top of every withdrawal.
+    pub(crate) withdrawal_fee: FeePolicy,
+    /// Client whose Account collects the withdrawal fees.
+    pub(crate) fee_client: u16,
+    /// Held amount above which an alert is recorded in the audit log, if any.
+    pub(crate) held_alert_threshold: Option<f32>,
+    /// Largest number of disputes a client may have open at once, if any.
+    pub(crate) max_open_disputes: Option<usize>,
+    /// Largest number of Accounts the service keeps, if any.
+    pub(crate) max_accounts: Option<usize>,
+    /// Largest number of deposits and withdrawals kept for disputes, if any.
+    pub(crate) max_ledger_size: Option<usize>,
+    /// Financial operations which are still allowed on a locked Account.
+    #[serde(serialize_with = "sorted")]
+    pub(crate) allowed_on_locked: HashSet<TransactionType>,
+    /// Whether only deposits may be disputed.
+    pub(crate) strict_dispute: bool,
+    /// Whether replayed deposits and withdrawals are ignored instead of applied again.
+    pub(crate) idempotent: bool,
+    /// Whether balances are rounded to the scale after every operation.
+    pub(crate) round_balances: bool,
+    /// Whether the CSV report ends with a row of the totals of all its Accounts.
+    pub(crate) with_summary: bool,
+    /// Whether the balances after every transaction are kept per client.
+    pub(crate) track_balance_history: bool,
+    /// How withdrawals for clients without an Account are handled.
+    pub(crate) missing_account_policy: MissingAccountPolicy,
+    /// Most verbose level the service logs at. Messages below it are never
+    /// handed to the global logger.
+    pub(crate) log_level: LevelFilter,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            rounding_mode: RoundingMode::default(),
+            scale: 4,
+            epsilon: 0.00001,
+            max_withdrawal: None,
+            withdrawal_fee: FeePolicy::default(),
+            fee_client: 0,
+            held_alert_threshold: None,
+            max_open_disputes: None,
+            max_accounts: None,
+            max_ledger_size: None,
+            allowed_on_locked: HashSet::new(),
+            strict_dispute: false,
+            idempotent: false,
+            round_balances: false,
+            with_summary: false,
+            track_balance_history: false,
+            missing_account_policy: MissingAccountPolicy::default(),
+            log_level: LevelFilter::Trace,
+        }
+    }
+}
+/// Writes the operations in a fixed order, so that the same settings
+/// always produce the same snapshot.
+fn sorted<S: Serializer>(types: &HashSet<TransactionType>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut types: Vec<&TransactionType> = types.iter().collect();
+    types.sort_by_cached_key(|_type| format!("{:?}", _type));
+    serializer.collect_seq(types)
+}
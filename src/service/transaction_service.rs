@@ -1,500 +1,5297 @@
-use std::collections::{HashMap, HashSet};
-use crate::{Account, TransactionError, TransactionRecord, Result, TransactionType, Transaction, TransactionEntry};
-use log::{info, error};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use crate::{Account, AccountFilter, AccountReportRow, AccountStore, BalancePoint, FeePolicy, MissingAccountPolicy, AccountSnapshot, AccountStatus, AuditEvent, DisputeState, EngineSnapshot, EngineStats, RoundingMode, SystemTotals, TransactionServiceBuilder, TransactionError, TransactionRecord, Result, TransactionType, Transaction, TransactionEntry, TransferEntry, HoldEntry, ReconciliationMismatch, Discrepancy};
+use log::{info, error, Level, LevelFilter};
+use super::settings::Settings;
 
+/// Callback invoked with every record passed to `process` and its outcome.
+type ProcessHook = Box<dyn FnMut(&TransactionRecord, &Result<AccountSnapshot>) + Send>;
+
+/// Check which may veto a record before it is applied, by returning the error to fail with.
+type RiskCheck<S> = Box<dyn FnMut(&TransactionRecord, &TransactionService<S>) -> Option<TransactionError> + Send>;
 
 /// This service is responsible for implementing and handling
 /// different types of transactions. Also keeps tracks the ongoing
-/// transactions and accounts involved.
+/// transactions and accounts involved. Accounts are kept in an in-memory
+/// `HashMap` unless a different `AccountStore` is provided.
 #[derive(Default)]
-pub struct TransactionService {
+pub struct TransactionService<S: AccountStore = HashMap<u16, Account>> {
     /// Keeps a track of all the Accounts in the system.
-    account_ledger: HashMap<u16, Account>,
+    account_ledger: S,
     /// Keeps a track of transactions related to deposits
     /// and withdrawals.
     transaction_ledger: HashMap<u32, TransactionEntry>,
-    /// Keeps a track of all open disputes in the system.
-    dispute_ledger: HashSet<u32>,
+    /// Keeps a track of manual corrections made by operators for auditing.
+    adjustment_ledger: HashMap<u32, TransactionEntry>,
+    /// Keeps a track of transfers between Accounts for auditing.
+    transfer_ledger: HashMap<u32, TransferEntry>,
+    /// Keeps a track of the interest credited to each client.
+    accrued_interest: HashMap<u16, f32>,
+    /// Keeps a track of the holds placed on available funds,
+    /// including the ones that were captured.
+    holds: HashMap<u32, HoldEntry>,
+    /// Keeps a track of the dispute state of every transaction
+    /// that was ever disputed.
+    dispute_ledger: HashMap<u32, DisputeState>,
+    /// Keeps a track of how far each client is allowed to
+    /// overdraw their Account. Clients without an entry can't overdraw.
+    overdraft_limits: HashMap<u16, f32>,
+    /// Keeps a track of the clients whose Accounts are frozen during
+    /// an investigation. Unlike a lock, a freeze is lifted by a thaw.
+    frozen_accounts: HashSet<u16>,
+    /// Keeps a track of the deposits and withdrawals made by each
+    /// client, in the order they were made.
+    client_transactions: HashMap<u16, Vec<u32>>,
+    /// Keeps a track of the deposits and withdrawals of all clients,
+    /// in the order they were made, so the latest one can be undone.
+    history: Vec<u32>,
+    /// Keeps the deposits and withdrawals of a capped transaction ledger
+    /// in the order they are evicted in.
+    ledger_order: VecDeque<u32>,
+    /// Keeps a track of the deposits and withdrawals evicted from a
+    /// capped transaction ledger. Only the latest ones are remembered,
+    /// as many as the ledger keeps.
+    expired_transactions: HashSet<u32>,
+    /// Keeps the remembered evictions in the order they happened in,
+    /// so that the oldest one is forgotten first.
+    expired_order: VecDeque<u32>,
+    /// Keeps the amount moved by the evicted transactions of each client,
+    /// and by the transactions of closed Accounts, so that reconciling
+    /// still accounts for them.
+    expired_totals: HashMap<u16, f32>,
+    /// Keeps a track of the client and id of the deposits and withdrawals already
+    /// applied, so that replays can be ignored when the service is idempotent.
+    applied_records: HashSet<(u16, u32)>,
+    /// Keeps a track of the balances of each client after each of their
+    /// transactions, when enabled through the settings.
+    balance_history: HashMap<u16, Vec<BalancePoint>>,
+    /// Keeps a count of the outcomes of all processed transactions.
+    stats: EngineStats,
+    /// Optional behaviours configured through `TransactionServiceBuilder`.
+    settings: Settings,
+    /// Keeps a trail of every change applied to the Accounts.
+    audit_log: Vec<AuditEvent>,
+    /// Callbacks registered through `on_processed`.
+    hooks: Vec<ProcessHook>,
+    /// Checks registered through `add_risk_check`.
+    risk_checks: Vec<RiskCheck<S>>,
 }
 
-impl TransactionService {
+impl<S: AccountStore> TransactionService<S> {
+    /// Creates a service with the given store and behaviours.
+    pub(crate) fn configured(account_ledger: S, settings: Settings, overdraft_limits: HashMap<u16, f32>) -> Self {
+        TransactionService {
+            account_ledger,
+            transaction_ledger: HashMap::new(),
+            adjustment_ledger: HashMap::new(),
+            transfer_ledger: HashMap::new(),
+            accrued_interest: HashMap::new(),
+            holds: HashMap::new(),
+            dispute_ledger: HashMap::new(),
+            overdraft_limits,
+            frozen_accounts: HashSet::new(),
+            client_transactions: HashMap::new(),
+            history: Vec::new(),
+            ledger_order: VecDeque::new(),
+            expired_transactions: HashSet::new(),
+            expired_order: VecDeque::new(),
+            expired_totals: HashMap::new(),
+            applied_records: HashSet::new(),
+            balance_history: HashMap::new(),
+            stats: EngineStats::default(),
+            settings,
+            audit_log: Vec::new(),
+            hooks: Vec::new(),
+            risk_checks: Vec::new(),
+        }
+    }
+
     /// Takes in a `TransactionRecord` and processes it based on the
-    /// transaction type.
-    pub fn process(&mut self, record: TransactionRecord) -> Result<()> {
-        info!("Processing transaction {} of type {:?} for client {}", record.tx, record._type, record.client);
-        if let Some(_) = self.account_ledger.get(&record.client)
-            .filter(|x| x.locked == true) {
-            error!("Given transaction cannot occur since the Account is locked");
-            return Err(TransactionError::LockedAccount);
+    /// transaction type. On success the state of the Account that was
+    /// changed is returned. Locked Accounts only accept non-financial
+    /// operations such as `TransactionType::UNLOCK`.
+    pub fn process(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let hooked = (!self.hooks.is_empty()).then(|| record.clone());
+        let result = self.process_record(record);
+        if let Some(record) = hooked {
+            for hook in self.hooks.iter_mut() {
+                hook(&record, &result);
+            }
+        }
+        result
+    }
+
+    /// Registers a callback that runs after every record passed to `process`,
+    /// whether it succeeded or failed. Callbacks run in the order they were registered.
+    pub fn on_processed<F>(&mut self, hook: F)
+    where
+        F: FnMut(&TransactionRecord, &Result<AccountSnapshot>) + Send + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Registers a check that runs before every record is applied, e.g. a
+    /// velocity check or a blocklist. When it returns an error, `process`
+    /// fails with it without changing any state. Checks run in the order they
+    /// were registered and the first error wins. `validate` doesn't run them.
+    pub fn add_risk_check<F>(&mut self, check: F)
+    where
+        F: FnMut(&TransactionRecord, &TransactionService<S>) -> Option<TransactionError> + Send + 'static,
+    {
+        self.risk_checks.push(Box::new(check));
+    }
+
+    /// Returns the error of the first risk check which blocks the record.
+    fn run_risk_checks(&mut self, record: &TransactionRecord) -> Option<TransactionError> {
+        // The checks are taken out while they run, since they look at the service.
+        let mut checks = std::mem::take(&mut self.risk_checks);
+        let service = &*self;
+        let blocked = checks.iter_mut().find_map(|check| check(record, service));
+        self.risk_checks = checks;
+        blocked
+    }
+
+    /// Applies the record and keeps the audit log and stats up to date.
+    fn process_record(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        if self.logs(Level::Info) {
+            info!("Processing transaction {} of type {:?} for client {}", record.tx, record._type, record.client);
+        }
+        let (_type, client, tx, amount) = (record._type.clone(), record.client, record.tx, record.amount);
+        if self.is_replay(&record) {
+            if self.logs(Level::Info) {
+                info!("Ignoring replayed transaction {}", tx);
+            }
+            let result = self.account_snapshot(client, tx);
+            self.stats.record(_type, result.is_ok());
+            return result;
+        }
+        let (target, timestamp, currency, reference) = (record.target_client, record.timestamp, record.currency.clone(), record.reference.clone());
+        let (held_before, locked_before) = self.account_ledger.get(&client)
+            .map_or((0.00, false), |acc| (acc.held(), acc.locked()));
+        let mut result = match self.run_risk_checks(&record) {
+            Some(err) => Err(err),
+            None => self.apply(record),
+        };
+        if result.is_ok() && self.settings.round_balances {
+            self.round_balances([Some(client), target, Some(self.settings.fee_client)]);
+            if let Some(account) = self.account_ledger.get(&client) {
+                result = Ok(AccountSnapshot::from(account));
+            }
+        }
+        if let Ok(account) = &result {
+            if self.settings.idempotent && matches!(_type, TransactionType::DEPOSIT | TransactionType::WITHDRAWAL) {
+                self.applied_records.insert((client, tx));
+            }
+            self.debug_assert_invariants(client);
+            if let Some(target) = target {
+                self.debug_assert_invariants(target);
+            }
+            if self.settings.track_balance_history {
+                self.record_balance(client, tx);
+                if let Some(target) = target {
+                    self.record_balance(target, tx);
+                }
+            }
+            self.audit_log.push(AuditEvent::Transaction { _type: _type.clone(), client, tx, amount, target, currency, reference, timestamp, account: account.clone() });
+            self.check_held_threshold(client, held_before, account.held);
+            if account.locked && !locked_before {
+                self.audit_log.push(AuditEvent::AccountLocked { client, tx });
+            }
+        }
+        self.stats.record(_type, result.is_ok());
+        result
+    }
+
+    /// Async counterpart of `process` for callers running inside a
+    /// Tokio runtime. Yields to the runtime before processing so that
+    /// long streams of records don't starve other tasks.
+    #[cfg(feature = "tokio")]
+    pub async fn process_async(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        tokio::task::yield_now().await;
+        self.process(record)
+    }
+
+    /// Awaits every record of the stream in turn, e.g. from a message queue,
+    /// and pairs each `tx` with its outcome like `process_all`.
+    #[cfg(feature = "tokio")]
+    pub async fn process_stream<St: futures::Stream<Item = TransactionRecord>>(&mut self, stream: St) -> Vec<(u32, Result<AccountSnapshot>)> {
+        use futures::StreamExt;
+
+        let mut stream = std::pin::pin!(stream);
+        let mut results = Vec::new();
+        while let Some(record) = stream.next().await {
+            let tx = record.tx;
+            results.push((tx, self.process_async(record).await));
+        }
+        results
+    }
+
+    /// Verifies that every Account's total is the sum of its available
+    /// and held amounts, failing with the first Account that isn't.
+    pub fn check_invariants(&self) -> Result<()> {
+        match self.account_ledger.iter().find(|(_, acc)| !acc.is_balanced()) {
+            Some((client, _)) => Err(TransactionError::UnbalancedAccount(*client)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the audit log ordered by the time of each transaction.
+    /// Events without a timestamp stay right after the event applied
+    /// before them, so a log without any timestamps keeps its order.
+    pub fn audit_log_by_time(&self) -> Vec<&AuditEvent> {
+        let mut last = i64::MIN;
+        let mut events: Vec<(i64, &AuditEvent)> = self.audit_log.iter()
+            .map(|event| {
+                if let AuditEvent::Transaction { timestamp: Some(timestamp), .. } = event {
+                    last = *timestamp;
+                }
+                (last, event)
+            })
+            .collect();
+        events.sort_by_key(|(timestamp, _)| *timestamp);
+        events.into_iter().map(|(_, event)| event).collect()
+    }
+
+    /// Whether the transaction currently has an open dispute.
+    pub fn is_disputed(&self, tx: u32) -> bool {
+        self.dispute_ledger.get(&tx).is_some_and(|state| state.is_open())
+    }
+
+    /// Whether the transaction was charged back in full or its chargeback
+    /// was appealed, after which it can no longer be disputed.
+    pub fn is_finalized(&self, tx: u32) -> bool {
+        self.dispute_ledger.get(&tx) == Some(&DisputeState::Appealed) || self.transaction_ledger.get(&tx)
+            .is_some_and(|t_entry| t_entry.charged_back != 0.00 && (t_entry.amount - t_entry.charged_back).abs() <= self.settings.epsilon)
+    }
+
+    /// Returns the reference given with a deposit, withdrawal or adjustment,
+    /// so it can be reconciled with the system it came from.
+    pub fn reference(&self, tx: u32) -> Option<&str> {
+        self.transaction_ledger.get(&tx)
+            .or_else(|| self.adjustment_ledger.get(&tx))
+            .and_then(|t_entry| t_entry.reference.as_deref())
+    }
+
+    /// Returns the ids of the client's deposits and withdrawals,
+    /// in the order they were made.
+    pub fn client_transactions(&self, client: u16) -> Vec<u32> {
+        self.client_transactions.get(&client).cloned().unwrap_or_default()
+    }
+
+    /// Recomputes the client's net position, the total of their Account,
+    /// from the ledgers alone: deposits minus withdrawals and what was charged
+    /// back, along with adjustments, transfers, fees, interest and captured holds.
+    /// A difference with the stored `available + held` points to a bug.
+    /// Returns `None` for clients without an Account.
+    pub fn net_position(&self, client: u16) -> Option<f32> {
+        if !self.account_ledger.contains(&client) {
+            return None;
+        }
+        Some(self.expected_totals().get(&client).copied().unwrap_or_default())
+    }
+
+    /// Totals of every client according to the ledgers. Disputes of deposits
+    /// only move money between available and held, so they don't affect the
+    /// total, whereas the amount of a disputed withdrawal is held on top of it.
+    fn expected_totals(&self) -> HashMap<u16, f32> {
+        let mut expected: HashMap<u16, f32> = HashMap::new();
+        for entry in self.transaction_ledger.values() {
+            *expected.entry(entry.client).or_default() += entry.amount - entry.charged_back + entry.refunded;
+        }
+        for entry in self.adjustment_ledger.values() {
+            *expected.entry(entry.client).or_default() += entry.amount;
+        }
+        for entry in self.transfer_ledger.values() {
+            *expected.entry(entry.client).or_default() -= entry.amount;
+            *expected.entry(entry.target).or_default() += entry.amount;
+        }
+        for (client, amount) in &self.expired_totals {
+            *expected.entry(*client).or_default() += amount;
+        }
+        for (client, interest) in &self.accrued_interest {
+            *expected.entry(*client).or_default() += interest;
+        }
+        for entry in self.holds.values().filter(|entry| entry.captured) {
+            *expected.entry(entry.client).or_default() -= entry.amount;
+        }
+        for (tx, state) in &self.dispute_ledger {
+            if let (DisputeState::Disputed(amount), Some(entry)) = (state, self.transaction_ledger.get(tx)) {
+                if *amount < 0.00 {
+                    *expected.entry(entry.client).or_default() -= amount;
+                }
+            }
+        }
+        expected
+    }
+
+    /// Recomputes the total of every Account from the money the ledgers say
+    /// was moved in and out of it, and reports the Accounts that differ.
+    pub fn reconcile(&self) -> std::result::Result<(), ReconciliationMismatch> {
+        let mut expected = self.expected_totals();
+        for (client, _) in self.account_ledger.iter() {
+            expected.entry(*client).or_default();
         }
 
+        let mut discrepancies: Vec<Discrepancy> = expected.into_iter()
+            .map(|(client, expected)| Discrepancy {
+                client,
+                expected,
+                actual: self.account_ledger.get(&client).map_or(0.00, |acc| acc.total()),
+            })
+            .filter(|d| {
+                let tolerance = 0.0001_f32.max(d.expected.abs() * f32::EPSILON * 16.0);
+                (d.expected - d.actual).abs() > tolerance
+            })
+            .collect();
+        if discrepancies.is_empty() {
+            return Ok(());
+        }
+        discrepancies.sort_by_key(|d| d.client);
+        Err(ReconciliationMismatch { discrepancies })
+    }
+
+    /// Returns the balances of the client's Account after each of their
+    /// processed transactions, oldest first. Transfers are part of the
+    /// history of both clients. Empty unless the history is tracked,
+    /// see `TransactionServiceBuilder::track_balance_history`.
+    pub fn balance_history(&self, client: u16) -> Vec<BalancePoint> {
+        self.balance_history.get(&client).cloned().unwrap_or_default()
+    }
+
+    /// Returns every change applied so far, in the order it was applied.
+    pub fn audit_log(&self) -> &[AuditEvent] {
+        &self.audit_log
+    }
+
+    /// Returns the outcomes of all transactions processed so far.
+    pub fn stats(&self) -> EngineStats {
+        self.stats.clone()
+    }
+
+    /// Dispatches the record to the operation matching its type.
+    fn apply(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        Self::validate_supported(&record)?;
+        self.validate_not_finalized(&record)?;
+        self.validate_unlocked(&record)?;
+        self.validate_scale(&record)?;
+        self.validate_range(&record)?;
+        self.validate_currency(&record)?;
+
         match record._type {
             TransactionType::DEPOSIT => self.deposit(record),
             TransactionType::WITHDRAWAL => self.withdrawal(record),
             TransactionType::DISPUTE => self.dispute(record),
             TransactionType::RESOLVE => self.resolve(record),
             TransactionType::CHARGEBACK => self.chargeback(record),
+            TransactionType::SOFT_CHARGEBACK => self.soft_chargeback(record),
+            TransactionType::TRANSFER => self.transfer(record),
+            TransactionType::UNLOCK => self.unlock(record),
+            TransactionType::CLOSE => self.close(record),
+            TransactionType::ADJUSTMENT => self.adjustment(record),
+            TransactionType::REOPEN => self.reopen(record),
+            TransactionType::APPEAL => self.appeal(record),
+            TransactionType::REFUND => self.refund(record),
+            TransactionType::Unknown(name) => Err(TransactionError::UnsupportedTransactionType(name)),
+        }
+    }
+
+    /// Runs the same checks as `process` for the given record and returns
+    /// the error `process` would fail with, without changing any state.
+    pub fn validate(&self, record: &TransactionRecord) -> Result<()> {
+        Self::validate_supported(record)?;
+        self.validate_not_finalized(record)?;
+        self.validate_unlocked(record)?;
+        self.validate_scale(record)?;
+        self.validate_range(record)?;
+        self.validate_currency(record)?;
+
+        match &record._type {
+            TransactionType::DEPOSIT => self.validate_deposit(record).map(|_| ()),
+            TransactionType::WITHDRAWAL => self.validate_withdrawal(record).map(|_| ()),
+            TransactionType::DISPUTE => self.validate_dispute(record).map(|_| ()),
+            TransactionType::RESOLVE | TransactionType::CHARGEBACK | TransactionType::SOFT_CHARGEBACK => {
+                self.validate_dispute_settlement(record).map(|_| ())
+            }
+            TransactionType::TRANSFER => self.validate_transfer(record).map(|_| ()),
+            TransactionType::UNLOCK => self.validate_unlock(record),
+            TransactionType::CLOSE => self.validate_close(record).map(|_| ()),
+            TransactionType::ADJUSTMENT => self.validate_adjustment(record).map(|_| ()),
+            TransactionType::REOPEN => self.validate_reopen(record).map(|_| ()),
+            TransactionType::APPEAL => self.validate_appeal(record).map(|_| ()),
+            TransactionType::REFUND => self.validate_refund(record).map(|_| ()),
+            TransactionType::Unknown(name) => Err(TransactionError::UnsupportedTransactionType(name.clone())),
+        }
+    }
+
+    /// Processes every record in order and pairs each `tx` with its outcome.
+    /// A failing record does not stop the remaining records from being processed.
+    pub fn process_all<I: IntoIterator<Item = TransactionRecord>>(&mut self, records: I) -> Vec<(u32, Result<AccountSnapshot>)> {
+        records
+            .into_iter()
+            .map(|record| (record.tx, self.process(record)))
+            .collect()
+    }
+
+    /// Resolves every open dispute of the client, oldest transaction id
+    /// first, and pairs each `tx` with its outcome. Each dispute is resolved
+    /// through `process` as if a resolve record had been submitted for it.
+    pub fn resolve_all(&mut self, client: u16) -> Vec<(u32, Result<AccountSnapshot>)> {
+        let mut disputed: Vec<u32> = self.dispute_ledger.iter()
+            .filter(|(tx, state)| state.is_open()
+                && self.transaction_ledger.get(tx).is_some_and(|t_entry| t_entry.client == client))
+            .map(|(tx, _)| *tx)
+            .collect();
+        disputed.sort_unstable();
+
+        let records = disputed.into_iter().map(|tx| TransactionRecord {
+            _type: TransactionType::RESOLVE,
+            client,
+            tx,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+        self.process_all(records)
+    }
+
+    /// Iterates over every Account in the system without consuming
+    /// the service. The order of iteration is not guaranteed.
+    pub fn accounts(&self) -> impl Iterator<Item = (&u16, &Account)> {
+        self.account_ledger.iter()
+    }
+
+    /// Tells whether the client has an Account and whether it can transact.
+    /// An Account which is both locked and frozen is reported as locked,
+    /// since thawing it alone doesn't let it transact again.
+    pub fn account_status(&self, client: u16) -> AccountStatus {
+        match self.account_ledger.get(&client) {
+            None => AccountStatus::Missing,
+            Some(account) if account.locked() => AccountStatus::Locked,
+            Some(_) if self.frozen_accounts.contains(&client) => AccountStatus::Frozen,
+            Some(_) => AccountStatus::Active,
+        }
+    }
+
+    /// Returns the clients whose Accounts are locked, sorted by client id.
+    pub fn locked_accounts(&self) -> Vec<u16> {
+        let mut clients: Vec<u16> = self.account_ledger.iter()
+            .filter(|(_, acc)| acc.locked())
+            .map(|(client, _)| *client)
+            .collect();
+        clients.sort_unstable();
+        clients
+    }
+
+    /// Sums the balances of every Account in the system.
+    pub fn totals(&self) -> SystemTotals {
+        self.account_ledger.iter()
+            .fold(SystemTotals::default(), |totals, (_, acc)| SystemTotals {
+                available: totals.available + acc.available(),
+                held: totals.held + acc.held(),
+                total: totals.total + acc.total(),
+                locked_accounts: totals.locked_accounts + usize::from(acc.locked()),
+            })
+    }
+
+    /// Captures the full state of the service so that it can be
+    /// persisted and restored later.
+    pub fn snapshot(&self) -> EngineSnapshot {
+        let mut applied_records: Vec<(u16, u32)> = self.applied_records.iter().copied().collect();
+        applied_records.sort_unstable();
+        EngineSnapshot {
+            account_ledger: self.account_ledger.iter()
+                .map(|(client, acc)| (*client, acc.clone()))
+                .collect(),
+            transaction_ledger: self.transaction_ledger.clone(),
+            adjustment_ledger: self.adjustment_ledger.clone(),
+            transfer_ledger: self.transfer_ledger.clone(),
+            accrued_interest: self.accrued_interest.clone(),
+            holds: self.holds.clone(),
+            dispute_ledger: self.dispute_ledger.clone(),
+            overdraft_limits: self.overdraft_limits.clone(),
+            client_transactions: self.client_transactions.clone(),
+            history: self.history.clone(),
+            frozen_accounts: self.frozen_accounts.iter().copied().collect(),
+            applied_records,
+            expired_transactions: self.expired_order.iter().copied().collect(),
+            expired_totals: self.expired_totals.clone(),
+            settings: self.settings.clone(),
+        }
+    }
+
+    /// Empties every ledger, the audit log and the stats, so that the service
+    /// can be reused for another run without reallocating. The settings,
+    /// overdraft limits, hooks and risk checks are kept.
+    pub fn clear(&mut self) {
+        self.account_ledger.clear();
+        self.transaction_ledger.clear();
+        self.adjustment_ledger.clear();
+        self.transfer_ledger.clear();
+        self.accrued_interest.clear();
+        self.holds.clear();
+        self.dispute_ledger.clear();
+        self.frozen_accounts.clear();
+        self.client_transactions.clear();
+        self.history.clear();
+        self.ledger_order.clear();
+        self.expired_transactions.clear();
+        self.expired_order.clear();
+        self.expired_totals.clear();
+        self.applied_records.clear();
+        self.balance_history.clear();
+        self.stats = EngineStats::default();
+        self.audit_log.clear();
+    }
+
+    /// Reverts the most recent deposit or withdrawal, including any fee it was
+    /// charged or refund it was given, and forgets about it. Transactions that were ever disputed
+    /// can't be undone, and neither can a deposit whose funds were spent.
+    /// The undo is recorded in the audit log, the stats no longer count the transaction,
+    /// and the balance history gets a point with the balances after the undo.
+    pub fn undo_last(&mut self) -> Result<()> {
+        let tx = match self.history.last() {
+            Some(tx) => *tx,
+            None => return Err(TransactionError::MissingTransaction),
+        };
+        if self.dispute_ledger.contains_key(&tx) {
+            return Err(TransactionError::DisputeAlreadyExists);
+        }
+        let entry = match self.transaction_ledger.get(&tx) {
+            Some(t_entry) => t_entry.clone(),
+            None => return Err(TransactionError::MissingTransaction),
+        };
+        let fee = if entry.amount < 0.00 { self.transfer_ledger.get(&tx).cloned() } else { None };
+        let fee_amount = fee.as_ref().map_or(0.00, |t_entry| t_entry.amount);
+
+        let account = match self.account_ledger.get(&entry.client) {
+            Some(account) => account.try_adjust(fee_amount - entry.amount - entry.refunded)?,
+            None => return Err(TransactionError::AccountNotFound(entry.client)),
+        };
+        if account.available() < 0.00 && entry.amount > 0.00 {
+            return Err(TransactionError::InsufficientFunds);
+        }
+        let fee_account = match &fee {
+            Some(t_entry) => match self.account_ledger.get(&t_entry.target) {
+                Some(fee_account) if fee_account.available() >= t_entry.amount => Some(fee_account.try_adjust(-t_entry.amount)?),
+                Some(_) => return Err(TransactionError::InsufficientFunds),
+                None => return Err(TransactionError::AccountNotFound(t_entry.target)),
+            },
+            None => None,
+        };
+
+        let snapshot = AccountSnapshot::from(&account);
+        self.account_ledger.insert(entry.client, account);
+        let fee_client = fee.as_ref().map(|t_entry| t_entry.target);
+        if let (Some(t_entry), Some(fee_account)) = (fee, fee_account) {
+            self.account_ledger.insert(t_entry.target, fee_account);
+            self.transfer_ledger.remove(&tx);
+        }
+        self.transaction_ledger.remove(&tx);
+        if let Some(transactions) = self.client_transactions.get_mut(&entry.client) {
+            transactions.retain(|client_tx| *client_tx != tx);
+        }
+        self.applied_records.remove(&(entry.client, tx));
+        self.history.pop();
+        let _type = if entry.is_withdrawal() { TransactionType::WITHDRAWAL } else { TransactionType::DEPOSIT };
+        self.stats.unrecord(_type);
+        if self.settings.track_balance_history {
+            self.record_balance(entry.client, tx);
+            if let Some(fee_client) = fee_client {
+                self.record_balance(fee_client, tx);
+            }
+        }
+        self.audit_log.push(AuditEvent::Undone { client: entry.client, tx, account: snapshot });
+
+        Ok(())
+    }
+
+    /// Reserves part of the client's available funds, e.g. for a card
+    /// authorization, until the hold is released or captured. Holds move
+    /// money between available and held like a dispute, but are tracked
+    /// apart from the dispute lifecycle. The amount is checked against the
+    /// scale and range like the amount of any record.
+    pub fn place_hold(&mut self, client: u16, tx: u32, amount: f32) -> Result<AccountSnapshot> {
+        if self.holds.contains_key(&tx) {
+            return Err(TransactionError::HoldAlreadyExists);
+        }
+        if amount <= 0.00 {
+            return Err(TransactionError::NonPositiveAmount);
+        }
+        self.validate_amount_scale(amount)?;
+        self.validate_amount_range(amount, [client])?;
+        let account = match self.account_ledger.get(&client) {
+            Some(account) if account.locked() => return Err(TransactionError::LockedAccount),
+            Some(_) if self.frozen_accounts.contains(&client) => return Err(TransactionError::FrozenAccount),
+            Some(account) if self.falls_short(account.available(), amount) => return Err(TransactionError::InsufficientFunds),
+            Some(account) => account.try_dispute(amount)?,
+            None => return Err(TransactionError::AccountNotFound(client)),
+        };
+
+        self.account_ledger.insert(client, account);
+        self.holds.insert(tx, HoldEntry { client, amount, captured: false });
+        self.debug_assert_invariants(client);
+        let snapshot = self.account_snapshot(client, tx)?;
+        self.audit_log.push(AuditEvent::HoldPlaced { client, tx, amount, account: snapshot.clone() });
+        Ok(snapshot)
+    }
+
+    /// Returns the funds of an open hold to the client's available balance.
+    pub fn release_hold(&mut self, client: u16, tx: u32) -> Result<AccountSnapshot> {
+        let (account, amount) = self.validate_hold(client, tx)?;
+        let updated_account = account.try_resolve(amount)?;
+
+        self.account_ledger.insert(client, updated_account);
+        self.holds.remove(&tx);
+        self.debug_assert_invariants(client);
+        let snapshot = self.account_snapshot(client, tx)?;
+        self.audit_log.push(AuditEvent::HoldReleased { client, tx, account: snapshot.clone() });
+        Ok(snapshot)
+    }
+
+    /// Takes the funds of an open hold from the client's Account,
+    /// debiting its total without locking it.
+    pub fn capture_hold(&mut self, client: u16, tx: u32) -> Result<AccountSnapshot> {
+        let (account, amount) = self.validate_hold(client, tx)?;
+        let updated_account = account.try_soft_chargeback(amount)?;
+
+        self.account_ledger.insert(client, updated_account);
+        if let Some(entry) = self.holds.get_mut(&tx) {
+            entry.captured = true;
+        }
+        self.debug_assert_invariants(client);
+        let snapshot = self.account_snapshot(client, tx)?;
+        self.audit_log.push(AuditEvent::HoldCaptured { client, tx, account: snapshot.clone() });
+        Ok(snapshot)
+    }
+
+    /// Suspends all financial operations for the client until it is thawed.
+    /// This is separate from the lock placed by a chargeback.
+    pub fn freeze(&mut self, client: u16) {
+        self.frozen_accounts.insert(client);
+    }
+
+    /// Lifts a freeze placed on the client. A thaw is always allowed,
+    /// even for clients that aren't frozen.
+    pub fn thaw(&mut self, client: u16) {
+        self.frozen_accounts.remove(&client);
+    }
+
+    /// Caps the number of disputes a client may have open at once. `None` removes the cap.
+    pub fn set_max_open_disputes(&mut self, max_open_disputes: Option<usize>) {
+        self.settings.max_open_disputes = max_open_disputes;
+    }
+
+    /// Caps the number of deposits and withdrawals kept for disputes, see
+    /// `TransactionServiceBuilder::max_ledger_size`. `None` removes the cap.
+    /// Transactions beyond a new cap are evicted with the next deposit or withdrawal.
+    pub fn set_max_ledger_size(&mut self, max_ledger_size: Option<usize>) {
+        self.settings.max_ledger_size = max_ledger_size;
+        self.ledger_order = self.history.iter()
+            .filter(|tx| self.transaction_ledger.contains_key(tx))
+            .copied()
+            .collect();
+    }
+
+    /// Caps the number of Accounts the service keeps. `None` removes the cap.
+    pub fn set_max_accounts(&mut self, max_accounts: Option<usize>) {
+        self.settings.max_accounts = max_accounts;
+    }
+
+    /// Records an alert whenever a dispute pushes an Account's held
+    /// amount above the threshold. `None` disables the alerts.
+    pub fn set_held_alert_threshold(&mut self, threshold: Option<f32>) {
+        self.settings.held_alert_threshold = threshold;
+    }
+
+    /// Replaces the operations which are still allowed on locked Accounts.
+    pub fn set_allowed_on_locked<I: IntoIterator<Item = TransactionType>>(&mut self, types: I) {
+        self.settings.allowed_on_locked = types.into_iter().collect();
+    }
+
+    /// Allows or forbids disputing withdrawals.
+    pub fn set_strict_dispute(&mut self, strict_dispute: bool) {
+        self.settings.strict_dispute = strict_dispute;
+    }
+
+    /// Makes replayed deposits and withdrawals succeed without being applied again.
+    pub fn set_idempotent(&mut self, idempotent: bool) {
+        self.settings.idempotent = idempotent;
+    }
+
+    /// Turns rounding the stored balances to the scale on or off.
+    pub fn set_round_balances(&mut self, round_balances: bool) {
+        self.settings.round_balances = round_balances;
+    }
+
+    /// Serializes the full state of the service as JSON, see `snapshot`.
+    /// Map keys are sorted so that the same state always produces the same JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&serde_json::to_value(self.snapshot())?)
+    }
+
+    /// Serializes the full state of the service with bincode, which is far
+    /// more compact than `to_json` for services with many Accounts.
+    pub fn to_bincode(&self) -> Vec<u8> {
+        bincode::serialize(&self.snapshot()).expect("the snapshot only holds types bincode can encode")
+    }
+
+    /// Allows the client to withdraw until their available balance
+    /// reaches `-limit`.
+    pub fn set_overdraft_limit(&mut self, client: u16, limit: f32) {
+        self.overdraft_limits.insert(client, limit);
+    }
+
+    /// Opens an empty Account for the client ahead of any deposit.
+    pub fn open_account(&mut self, client: u16) -> Result<AccountSnapshot> {
+        if self.account_ledger.contains(&client) {
+            return Err(TransactionError::AccountAlreadyExists(client));
+        }
+        self.validate_capacity(client)?;
+
+        let account = Account::new(client);
+        let snapshot = AccountSnapshot::from(&account);
+        self.account_ledger.insert(client, account);
+
+        Ok(snapshot)
+    }
+
+    /// Credits the client's Account with interest on its available balance.
+    /// Overdrawn Accounts earn no interest, rather than being charged it.
+    /// The interest is rounded to the configured scale like any other amount.
+    /// Negative rates are rejected, since interest never debits an Account,
+    /// and non-finite ones fail with `AmountOutOfRange` like any other amount.
+    pub fn accrue_interest(&mut self, client: u16, rate: f32) -> Result<AccountSnapshot> {
+        if rate < 0.00 {
+            return Err(TransactionError::InvalidRate);
         }
+        let account = match self.account_ledger.get(&client) {
+            Some(account) if account.locked() => return Err(TransactionError::LockedAccount),
+            Some(_) if self.frozen_accounts.contains(&client) => return Err(TransactionError::FrozenAccount),
+            Some(account) => account,
+            None => return Err(TransactionError::AccountNotFound(client)),
+        };
+
+        let amount = self.round_to_scale(account.available().max(0.00) * rate);
+        let updated_account = account.try_deposit(amount)?;
+        let snapshot = AccountSnapshot::from(&updated_account);
+        self.account_ledger.insert(client, updated_account);
+        *self.accrued_interest.entry(client).or_default() += amount;
+        self.debug_assert_invariants(client);
+        self.audit_log.push(AuditEvent::InterestAccrued { client, rate, amount, account: snapshot.clone() });
+
+        Ok(snapshot)
+    }
+
+    /// Changes how money is rounded in the report.
+    pub fn set_rounding_mode(&mut self, rounding_mode: RoundingMode) {
+        self.settings.rounding_mode = rounding_mode;
+    }
+
+    /// Starts or stops keeping the balance history of every client.
+    /// The history recorded so far is kept either way.
+    pub fn set_track_balance_history(&mut self, track: bool) {
+        self.settings.track_balance_history = track;
+    }
+
+    /// Starts or stops ending the CSV report with a `TOTAL` row.
+    pub fn set_with_summary(&mut self, with_summary: bool) {
+        self.settings.with_summary = with_summary;
+    }
+
+    /// Limits how verbose the service logs. `LevelFilter::Off` silences it.
+    pub fn set_log_level(&mut self, log_level: LevelFilter) {
+        self.settings.log_level = log_level;
+    }
+
+    /// Whether messages at the given level pass the configured log level.
+    pub(crate) fn logs(&self, level: Level) -> bool {
+        level <= self.settings.log_level
+    }
+
+    /// Changes the tolerance used when checking whether funds cover an amount.
+    pub fn set_epsilon(&mut self, epsilon: f32) {
+        self.settings.epsilon = epsilon;
+    }
+
+    /// Changes the number of decimals used for money.
+    pub fn set_scale(&mut self, scale: u32) {
+        self.settings.scale = scale;
+    }
+
+    /// Number of decimals used for money.
+    pub fn scale(&self) -> u32 {
+        self.settings.scale
+    }
+
+    /// Changes how withdrawals for clients without an Account are handled.
+    pub fn set_missing_account_policy(&mut self, policy: MissingAccountPolicy) {
+        self.settings.missing_account_policy = policy;
+    }
+
+    /// Charges a fee on top of every withdrawal, credited to the Account of `fee_client`.
+    /// Withdrawals of `fee_client` itself are not charged a fee.
+    pub fn set_withdrawal_fee(&mut self, policy: FeePolicy, fee_client: u16) {
+        self.settings.withdrawal_fee = policy;
+        self.settings.fee_client = fee_client;
+    }
+
+    /// Caps the amount of a single withdrawal. `None` removes the cap.
+    pub fn set_max_withdrawal(&mut self, max_withdrawal: Option<f32>) {
+        self.settings.max_withdrawal = max_withdrawal;
     }
 
     /// Generates the final output which displays different information
     /// about the Accounts that underwent the various transactions.
     pub fn generate_report(self) {
-        println!("client,available,held,total,locked");
-        self.account_ledger
-            .into_values()
-            .for_each(|acc| {
-                acc.print();
-            });
+        self.write_report(io::stdout().lock())
+            .expect("failed to write the report to stdout");
+    }
+
+    /// Writes the report to the given writer, one row per Account
+    /// sorted by client id so that the output is reproducible.
+    pub fn write_report<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.write_filtered_report(writer, AccountFilter::All)
     }
 
-    /// Common code pulled for Resolve and Chargeback. The only difference
-    /// between the two is how the accounts are changed in the end.
-    fn process_dispute(&mut self, record: &TransactionRecord) -> Result<()> {
-        if !self.dispute_ledger.contains(&record.tx) {
-            return Err(TransactionError::TransactionNotDisputed);
+    /// Writes the report like `write_report`, but only with the Accounts
+    /// included by the filter.
+    pub fn write_filtered_report<W: Write>(&self, writer: W, filter: AccountFilter) -> io::Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        writer.write_record(AccountReportRow::HEADER)?;
+        for row in self.report_rows(filter) {
+            writer.serialize(row)?;
         }
+        if self.settings.with_summary {
+            writer.write_record(self.summary_row(filter))?;
+        }
+        writer.flush()
+    }
 
-        match self.transaction_ledger.get(&record.tx) {
-            Some(t_entry) => {
-                if record.client != t_entry.client {
-                    return Err(TransactionError::MissingTransaction);
-                }
+    /// Row with the sums of the money of the Accounts included by the filter.
+    /// The locked column is left empty, since it can't be summed.
+    fn summary_row(&self, filter: AccountFilter) -> [String; 5] {
+        let (available, held, total) = self.account_ledger.iter()
+            .map(|(_, acc)| acc)
+            .filter(|acc| filter.matches(acc))
+            .fold((0.00, 0.00, 0.00), |(available, held, total), acc| {
+                (available + acc.available(), held + acc.held(), total + acc.total())
+            });
+        let format = |amount| self.settings.rounding_mode.format(amount, self.settings.scale as usize);
+        ["TOTAL".to_string(), format(available), format(held), format(total), String::new()]
+    }
 
-                match self.account_ledger.get(&record.client) {
-                    Some(account) => {
-                        let updated_account = self.update_dispute(account, t_entry.amount, &record._type)?;
-                        self.account_ledger.insert(record.client, updated_account);
-                    }
-                    None => {
-                        return Err(TransactionError::InvalidAccount);
-                    }
-                }
-                self.dispute_ledger.remove(&record.tx);
+    /// Returns the report with only the Accounts included by the filter,
+    /// e.g. the locked Accounts for a compliance review.
+    pub fn report_filtered(&self, filter: AccountFilter) -> String {
+        let mut report = Vec::new();
+        self.write_filtered_report(&mut report, filter)
+            .expect("writing to memory can't fail");
+        String::from_utf8(report).expect("the report is valid UTF-8")
+    }
+
+    /// Writes the same rows as `write_report` as a JSON array.
+    pub fn write_json_report<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        serde_json::to_writer(&mut writer, &self.report_rows(AccountFilter::All))?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    /// Rows of the report, sorted by client id so that the output is reproducible.
+    fn report_rows(&self, filter: AccountFilter) -> Vec<AccountReportRow> {
+        let mut accounts: Vec<&Account> = self.account_ledger.iter()
+            .map(|(_, acc)| acc)
+            .filter(|acc| filter.matches(acc))
+            .collect();
+        accounts.sort_by_key(|acc| acc.client());
+        accounts.into_iter()
+            .map(|acc| AccountReportRow::new(acc, self.settings.rounding_mode, self.settings.scale as usize))
+            .collect()
+    }
+
+    /// Financial operations can't occur on a locked or frozen Account,
+    /// unless the settings allow the operation on locked Accounts.
+    fn validate_unlocked(&self, record: &TransactionRecord) -> Result<()> {
+        let guarded = record._type.is_financial() && !self.settings.allowed_on_locked.contains(&record._type);
+        if guarded && self.account_ledger.get(&record.client)
+            .is_some_and(|x| x.locked()) {
+            if self.logs(Level::Error) {
+                error!("Given transaction cannot occur since the Account is locked");
             }
-            None => {
-                return Err(TransactionError::MissingTransaction);
+            return Err(TransactionError::LockedAccount);
+        }
+        if record._type.is_financial() && self.frozen_accounts.contains(&record.client) {
+            if self.logs(Level::Error) {
+                error!("Given transaction cannot occur since the Account is frozen");
             }
-        };
-
+            return Err(TransactionError::FrozenAccount);
+        }
         Ok(())
     }
-    fn update_dispute(&self, account: &Account, amount: f32, _type: &TransactionType) -> Result<Account> {
-        match _type {
-            TransactionType::RESOLVE => Ok(account.resolve(amount)),
-            TransactionType::CHARGEBACK => Ok(account.chargeback(amount)),
-            _ => Err(TransactionError::InvalidOperation)
+
+    /// Amounts can't be more precise than the money they represent.
+    /// The decimals are counted on the shortest representation of the
+    /// amount so that `1.1` isn't mistaken for its binary approximation.
+    fn validate_scale(&self, record: &TransactionRecord) -> Result<()> {
+        match record.amount {
+            Some(amount) => self.validate_amount_scale(amount),
+            None => Ok(()),
         }
     }
-}
 
-impl Transaction<TransactionRecord> for TransactionService {
-    fn deposit(&mut self, record: TransactionRecord) -> Result<()> {
-        if let Some(amount) = record.amount {
-            match self.account_ledger.get(&record.client) {
-                Some(account) => {
-                    let updated_account = account.deposit(amount);
-                    self.account_ledger.insert(record.client, updated_account);
-                }
-                None => {
-                    let account = Account {
-                        client: record.client,
-                        available: amount,
-                        held: 0.0,
-                        total: amount,
-                        locked: false,
-                    };
-                    self.account_ledger.insert(record.client, account);
-                }
-            };
-            self.transaction_ledger.insert(record.tx, TransactionEntry { client: record.client, amount });
-            Ok(())
-        } else {
-            return Err(TransactionError::MissingAmount);
+    /// Checks a single amount against the scale, see `validate_scale`.
+    fn validate_amount_scale(&self, amount: f32) -> Result<()> {
+        validate_decimals(&amount.abs().to_string(), self.settings.scale)
+    }
+
+    /// Amounts must be finite, and adding them to the balances of the Accounts
+    /// they change must stay finite too, since overflowing `f32` would
+    /// silently corrupt the balances.
+    fn validate_range(&self, record: &TransactionRecord) -> Result<()> {
+        match record.amount {
+            Some(amount) => self.validate_amount_range(amount, Some(record.client).into_iter().chain(record.target_client)),
+            None => Ok(()),
         }
     }
 
-    fn withdrawal(&mut self, record: TransactionRecord) -> Result<()> {
-        if let Some(amount) = record.amount {
-            if let Some(_) = self.account_ledger.get(&record.client)
-                .filter(|acc| acc.available - amount < 0.00) {
-                return Err(TransactionError::InsufficientFunds);
-            }
+    /// Checks a single amount against the balances of the clients, see `validate_range`.
+    fn validate_amount_range<I: IntoIterator<Item = u16>>(&self, amount: f32, clients: I) -> Result<()> {
+        if !amount.is_finite() {
+            return Err(TransactionError::AmountOutOfRange);
+        }
+        let fits = |client: u16| self.account_ledger.get(&client).is_none_or(|acc| {
+            [acc.available(), acc.held(), acc.total()].iter()
+                .all(|balance| (balance.abs() + amount.abs()).is_finite())
+        });
+        if !clients.into_iter().all(fits) {
+            return Err(TransactionError::AmountOutOfRange);
+        }
+        Ok(())
+    }
 
-            match self.account_ledger.get(&record.client) {
-                Some(account) => {
-                    let updated_account = account.withdrawal(amount);
-                    self.account_ledger.insert(record.client, updated_account);
-                    self.transaction_ledger.insert(record.tx, TransactionEntry { client: record.client, amount: -amount });
-                }
-                None => {
-                    return Err(TransactionError::InvalidAccount);
-                }
-            };
-        } else {
-            return Err(TransactionError::MissingAmount);
+    /// Records of an unknown type are rejected before any other check,
+    /// since none of them applies to a type this version doesn't know.
+    fn validate_supported(record: &TransactionRecord) -> Result<()> {
+        match &record._type {
+            TransactionType::Unknown(name) => Err(TransactionError::UnsupportedTransactionType(name.clone())),
+            _ => Ok(()),
         }
+    }
 
+    /// Checked before the lock, since the chargeback that finalized the
+    /// transaction usually locked the Account as well, and the lock would
+    /// hide the reason for rejecting the record. Transactions evicted from
+    /// a capped ledger are final as well, whatever their state was.
+    fn validate_not_finalized(&self, record: &TransactionRecord) -> Result<()> {
+        let references = matches!(record._type, TransactionType::DISPUTE | TransactionType::RESOLVE
+            | TransactionType::CHARGEBACK | TransactionType::SOFT_CHARGEBACK | TransactionType::REOPEN
+            | TransactionType::APPEAL | TransactionType::REFUND);
+        if references && self.expired_transactions.contains(&record.tx) {
+            return Err(TransactionError::TransactionExpired);
+        }
+        let settles = matches!(record._type, TransactionType::DISPUTE | TransactionType::RESOLVE
+            | TransactionType::CHARGEBACK | TransactionType::SOFT_CHARGEBACK);
+        let owned = self.transaction_ledger.get(&record.tx).is_some_and(|t_entry| t_entry.client == record.client);
+        if settles && owned && self.is_finalized(record.tx) {
+            return Err(TransactionError::TransactionFinalized);
+        }
         Ok(())
     }
 
-    fn dispute(&mut self, record: TransactionRecord) -> Result<()> {
-        if self.dispute_ledger.contains(&record.tx) {
-            return Err(TransactionError::DisputeAlreadyExists);
+    /// Money can't move between currencies, so the currency of a deposit,
+    /// withdrawal or transfer has to match the Accounts it changes. Records
+    /// and Accounts without a currency match any currency.
+    fn validate_currency(&self, record: &TransactionRecord) -> Result<()> {
+        let currency_of = |client: &u16| self.account_ledger.get(client).and_then(|acc| acc.currency());
+        let matching = |a: Option<&str>, b: Option<&str>| a.zip(b).is_none_or(|(a, b)| a == b);
+        let currency = record.currency.as_deref();
+        let matches = match record._type {
+            TransactionType::DEPOSIT | TransactionType::WITHDRAWAL => matching(currency, currency_of(&record.client)),
+            TransactionType::TRANSFER => {
+                let source = currency_of(&record.client);
+                matching(currency, source)
+                    && record.target_client.as_ref().is_none_or(|target| matching(currency.or(source), currency_of(target)))
+            }
+            _ => true,
+        };
+        if !matches {
+            return Err(TransactionError::CurrencyMismatch);
         }
+        Ok(())
+    }
 
-        match self.transaction_ledger.get(&record.tx) {
-            Some(t_entry) => {
-                if record.client != t_entry.client {
-                    return Err(TransactionError::MissingTransaction);
-                }
+    /// Returns the amount to be deposited, which has to be above zero.
+    fn validate_deposit(&self, record: &TransactionRecord) -> Result<f32> {
+        let amount = match record.amount {
+            Some(amount) => amount,
+            None => return Err(TransactionError::MissingAmount),
+        };
+        if amount <= 0.00 {
+            return Err(TransactionError::NonPositiveAmount);
+        }
+        self.validate_capacity(record.client)?;
 
-                if let Some(_) = self.account_ledger.get(&record.client)
-                    .filter(|acc| acc.available - t_entry.amount < 0.00) {
-                    return Err(TransactionError::InsufficientFunds);
-                }
+        Ok(amount)
+    }
+
+    /// A client without an Account can't get one once the configured cap is reached.
+    fn validate_capacity(&self, client: u16) -> Result<()> {
+        if let Some(max) = self.settings.max_accounts {
+            if !self.account_ledger.contains(&client) && self.account_ledger.iter().count() >= max {
+                return Err(TransactionError::AccountLimitReached(client));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rounds a calculated amount such as interest or a fee to the configured scale.
+    fn round_to_scale(&self, value: f32) -> f32 {
+        let factor = 10_f32.powi(self.settings.scale as i32);
+        (value * factor).round() / factor
+    }
+
+    /// Evicts the oldest deposits and withdrawals beyond the configured ledger
+    /// size. Transactions under dispute and the latest one are kept, and move
+    /// to the back of the queue so that they are only evicted once used up.
+    /// Evicted transactions are dropped from every ledger keyed by them, and
+    /// only as many of them as the ledger keeps are remembered as expired.
+    fn evict_expired(&mut self, latest: u32) {
+        let max = match self.settings.max_ledger_size {
+            Some(max) => max,
+            None => return,
+        };
+        self.ledger_order.push_back(latest);
+        let mut kept = 0;
+        while self.transaction_ledger.len() > max && kept < self.ledger_order.len() {
+            let tx = match self.ledger_order.pop_front() {
+                Some(tx) => tx,
+                None => break,
+            };
+            if tx == latest || self.is_disputed(tx) {
+                self.ledger_order.push_back(tx);
+                kept += 1;
+                continue;
+            }
+            // Transactions which were undone are no longer in the ledger.
+            if let Some(t_entry) = self.transaction_ledger.remove(&tx) {
+                *self.expired_totals.entry(t_entry.client).or_default() += t_entry.amount - t_entry.charged_back + t_entry.refunded;
+                self.dispute_ledger.remove(&tx);
+                self.history.retain(|history_tx| *history_tx != tx);
+                if let Some(transactions) = self.client_transactions.get_mut(&t_entry.client) {
+                    transactions.retain(|client_tx| *client_tx != tx);
+                }
+                self.applied_records.remove(&(t_entry.client, tx));
+                if self.expired_transactions.insert(tx) {
+                    self.expired_order.push_back(tx);
+                }
+            }
+        }
+        while self.expired_order.len() > max {
+            if let Some(tx) = self.expired_order.pop_front() {
+                self.expired_transactions.remove(&tx);
+            }
+        }
+    }
+
+    /// Drops the deposits and withdrawals of a closed Account, so that they can't
+    /// be disputed once a deposit reopens it. The money they moved is added to the
+    /// expired totals, so that reconciling still accounts for it.
+    fn forget_transactions(&mut self, client: u16) {
+        let transactions: HashSet<u32> = self.client_transactions.remove(&client).unwrap_or_default().into_iter().collect();
+        for tx in &transactions {
+            if let Some(t_entry) = self.transaction_ledger.remove(tx) {
+                *self.expired_totals.entry(client).or_default() += t_entry.amount - t_entry.charged_back + t_entry.refunded;
+                self.dispute_ledger.remove(tx);
+            }
+        }
+        self.history.retain(|tx| !transactions.contains(tx));
+        self.ledger_order.retain(|tx| !transactions.contains(tx));
+    }
+
+    /// Rounds the stored balances of the clients to the configured scale.
+    /// The fee client is included since withdrawals credit it as well.
+    fn round_balances<I: IntoIterator<Item = Option<u16>>>(&mut self, clients: I) {
+        for client in clients.into_iter().flatten() {
+            if let Some(account) = self.account_ledger.get(&client) {
+                let rounded = account.rounded(self.settings.scale);
+                self.account_ledger.insert(client, rounded);
+            }
+        }
+    }
+
+    /// Returns the amount to be withdrawn and the fee charged on top of it.
+    /// The amount has to be above zero, and the fee has to be covered by the
+    /// available funds as well. The client collecting the fees isn't charged
+    /// one, since it would pay itself.
+    fn validate_withdrawal(&self, record: &TransactionRecord) -> Result<(f32, f32)> {
+        let amount = match record.amount {
+            Some(amount) => amount,
+            None => return Err(TransactionError::MissingAmount),
+        };
+        if amount <= 0.00 {
+            return Err(TransactionError::NonPositiveAmount);
+        }
+
+        if self.settings.max_withdrawal.is_some_and(|max| amount > max) {
+            return Err(TransactionError::WithdrawalLimitExceeded);
+        }
+
+        let fee = if record.client == self.settings.fee_client {
+            0.00
+        } else {
+            self.round_to_scale(self.settings.withdrawal_fee.fee(amount))
+        };
+        let overdraft_limit = self.overdraft_limits.get(&record.client).copied().unwrap_or(0.00);
+        match self.account_ledger.get(&record.client) {
+            Some(account) => {
+                if self.falls_short(account.available() - amount - fee, -overdraft_limit) {
+                    return Err(TransactionError::InsufficientFunds);
+                }
+            }
+            None => match self.settings.missing_account_policy {
+                MissingAccountPolicy::Error => {
+                    return Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx });
+                }
+                MissingAccountPolicy::TreatAsEmpty => {
+                    if self.falls_short(-amount - fee, -overdraft_limit) {
+                        return Err(TransactionError::InsufficientFunds);
+                    }
+                    self.validate_capacity(record.client)?;
+                }
+            }
+        };
+        if fee != 0.00 {
+            self.validate_capacity(self.settings.fee_client)?;
+        }
+
+        Ok((amount, fee))
+    }
+
+    /// Returns the amount to be held for the dispute. A record without an amount
+    /// disputes everything that has not been charged back yet, otherwise only
+    /// the amount specified is disputed. The returned amount has the same sign
+    /// as the transaction amount.
+    fn validate_dispute(&self, record: &TransactionRecord) -> Result<f32> {
+        if self.dispute_ledger.get(&record.tx)
+            .is_some_and(|state| state.is_open()) {
+            return Err(TransactionError::DisputeAlreadyExists);
+        }
+
+        match self.transaction_ledger.get(&record.tx) {
+            Some(t_entry) => {
+                if record.client != t_entry.client {
+                    return Err(TransactionError::TransactionClientMismatch);
+                }
+                if self.settings.strict_dispute && t_entry.is_withdrawal() {
+                    return Err(TransactionError::CannotDisputeWithdrawal);
+                }
+                self.validate_open_disputes(record.client)?;
+
+                let disputable = t_entry.amount - t_entry.charged_back + t_entry.refunded;
+                let amount = match record.amount {
+                    Some(amount) => {
+                        if amount <= 0.00 {
+                            return Err(TransactionError::NonPositiveAmount);
+                        }
+                        if amount > disputable.abs() {
+                            return Err(TransactionError::DisputeExceedsTransaction);
+                        }
+                        amount * t_entry.amount.signum()
+                    }
+                    None => disputable,
+                };
+
+                match self.account_ledger.get(&record.client) {
+                    Some(account) => {
+                        if self.falls_short(account.available(), amount) {
+                            return Err(TransactionError::InsufficientFunds);
+                        }
+                    }
+                    None => {
+                        return Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx });
+                    }
+                }
+                Ok(amount)
+            }
+            None => Err(TransactionError::MissingTransaction)
+        }
+    }
+
+    /// Whether `value` is below `required` by more than the configured epsilon,
+    /// so that an exact balance isn't rejected over `f32` rounding.
+    fn falls_short(&self, value: f32, required: f32) -> bool {
+        value < required - self.settings.epsilon
+    }
+
+    /// Only an open hold of the given client can be released or captured.
+    /// Returns the client's Account and the held amount.
+    fn validate_hold(&self, client: u16, tx: u32) -> Result<(&Account, f32)> {
+        let amount = match self.holds.get(&tx) {
+            Some(entry) if entry.captured => return Err(TransactionError::MissingHold),
+            Some(entry) if entry.client != client => return Err(TransactionError::TransactionClientMismatch),
+            Some(entry) => entry.amount,
+            None => return Err(TransactionError::MissingHold),
+        };
+        match self.account_ledger.get(&client) {
+            Some(account) if self.falls_short(account.held(), amount) => Err(TransactionError::InsufficientHeldFunds),
+            Some(account) => Ok((account, amount)),
+            None => Err(TransactionError::AccountNotFound(client)),
+        }
+    }
+
+    /// A client can't open another dispute once the configured cap is reached.
+    fn validate_open_disputes(&self, client: u16) -> Result<()> {
+        if let Some(max) = self.settings.max_open_disputes {
+            let open = self.dispute_ledger.iter()
+                .filter(|(tx, state)| state.is_open()
+                    && self.transaction_ledger.get(tx).is_some_and(|t_entry| t_entry.client == client))
+                .count();
+            if open >= max {
+                return Err(TransactionError::TooManyOpenDisputes);
+            }
+        }
+        Ok(())
+    }
+
+    /// Common checks pulled for Resolve and Chargeback. The held amount
+    /// is checked so that settling a dispute can never drive it negative.
+    /// Returns the amount held by the dispute.
+    fn validate_dispute_settlement(&self, record: &TransactionRecord) -> Result<f32> {
+        if record.amount.is_some() {
+            return Err(TransactionError::UnexpectedAmount);
+        }
+
+        let amount = match self.dispute_ledger.get(&record.tx) {
+            Some(DisputeState::Disputed(amount)) => *amount,
+            _ => return Err(TransactionError::TransactionNotDisputed),
+        };
+
+        match self.transaction_ledger.get(&record.tx) {
+            Some(t_entry) => {
+                if record.client != t_entry.client {
+                    return Err(TransactionError::TransactionClientMismatch);
+                }
 
                 match self.account_ledger.get(&record.client) {
                     Some(account) => {
-                        let updated_account = account.dispute(t_entry.amount);
-                        self.account_ledger.insert(record.client, updated_account);
+                        if self.falls_short(account.held(), amount.abs()) {
+                            return Err(TransactionError::InsufficientHeldFunds);
+                        }
                     }
                     None => {
-                        return Err(TransactionError::InvalidAccount);
+                        return Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx });
                     }
                 }
-                self.dispute_ledger.insert(record.tx);
+                Ok(amount)
+            }
+            None => Err(TransactionError::MissingTransaction)
+        }
+    }
+
+    /// Only a resolved dispute can be reopened, since a charged back
+    /// transaction was already reversed. Returns the amount to hold again.
+    fn validate_reopen(&self, record: &TransactionRecord) -> Result<f32> {
+        if record.amount.is_some() {
+            return Err(TransactionError::UnexpectedAmount);
+        }
+
+        let amount = match self.dispute_ledger.get(&record.tx) {
+            Some(DisputeState::Resolved(amount)) => *amount,
+            Some(DisputeState::Disputed(_)) => return Err(TransactionError::DisputeAlreadyExists),
+            Some(DisputeState::ChargedBack | DisputeState::Appealed) => return Err(TransactionError::TransactionNotResolved),
+            None => return Err(TransactionError::TransactionNotDisputed),
+        };
+
+        match self.transaction_ledger.get(&record.tx) {
+            Some(t_entry) if t_entry.client != record.client => return Err(TransactionError::TransactionClientMismatch),
+            Some(_) => {}
+            None => return Err(TransactionError::MissingTransaction),
+        }
+        self.validate_open_disputes(record.client)?;
+
+        match self.account_ledger.get(&record.client) {
+            Some(account) => {
+                if self.falls_short(account.available(), amount) {
+                    return Err(TransactionError::InsufficientFunds);
+                }
+                Ok(amount)
+            }
+            None => Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx })
+        }
+    }
+
+    /// Returns the amount that was charged back, which the appeal re-credits.
+    fn validate_appeal(&self, record: &TransactionRecord) -> Result<f32> {
+        if record.amount.is_some() {
+            return Err(TransactionError::UnexpectedAmount);
+        }
+        if self.dispute_ledger.get(&record.tx) != Some(&DisputeState::ChargedBack) {
+            return Err(TransactionError::TransactionNotChargedBack);
+        }
+
+        let amount = match self.transaction_ledger.get(&record.tx) {
+            Some(t_entry) if t_entry.client != record.client => return Err(TransactionError::TransactionClientMismatch),
+            Some(t_entry) => t_entry.charged_back,
+            None => return Err(TransactionError::MissingTransaction),
+        };
+        if !self.account_ledger.contains(&record.client) {
+            return Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx });
+        }
+        Ok(amount)
+    }
+
+    /// Returns the amount to credit back, which defaults to what is
+    /// left to refund of the withdrawal. A given amount has to be above zero.
+    fn validate_refund(&self, record: &TransactionRecord) -> Result<f32> {
+        let t_entry = match self.transaction_ledger.get(&record.tx) {
+            Some(t_entry) if t_entry.client != record.client => return Err(TransactionError::TransactionClientMismatch),
+            Some(t_entry) => t_entry,
+            None => return Err(TransactionError::MissingTransaction),
+        };
+        if !t_entry.is_withdrawal() {
+            return Err(TransactionError::TransactionNotRefundable);
+        }
+        if self.dispute_ledger.get(&record.tx).is_some_and(|state| state.is_open()) {
+            return Err(TransactionError::DisputeAlreadyExists);
+        }
+
+        let refundable = t_entry.charged_back - t_entry.amount - t_entry.refunded;
+        if record.amount.is_some_and(|amount| amount <= 0.00) {
+            return Err(TransactionError::NonPositiveAmount);
+        }
+        let amount = record.amount.unwrap_or(refundable);
+        if amount - refundable > self.settings.epsilon {
+            return Err(TransactionError::RefundExceedsTransaction);
+        }
+        if !self.account_ledger.contains(&record.client) {
+            return Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx });
+        }
+        Ok(amount)
+    }
+
+    /// Returns the amount to be transferred and the client receiving it.
+    fn validate_transfer(&self, record: &TransactionRecord) -> Result<(f32, u16)> {
+        let amount = match record.amount {
+            Some(amount) => amount,
+            None => return Err(TransactionError::MissingAmount),
+        };
+        let target = match record.target_client {
+            Some(target) => target,
+            None => return Err(TransactionError::MissingTargetClient),
+        };
+        if target == record.client {
+            return Err(TransactionError::SelfTransfer);
+        }
+        if amount <= 0.00 {
+            return Err(TransactionError::NonPositiveAmount);
+        }
+
+        match self.account_ledger.get(&record.client) {
+            Some(account) => {
+                if self.falls_short(account.available(), amount) {
+                    return Err(TransactionError::InsufficientFunds);
+                }
             }
             None => {
-                return Err(TransactionError::MissingTransaction);
+                return Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx });
             }
         };
 
-        Ok(())
-    }
+        if self.account_ledger.get(&target)
+            .is_some_and(|acc| acc.locked()) {
+            return Err(TransactionError::LockedAccount);
+        }
+        if self.frozen_accounts.contains(&target) {
+            return Err(TransactionError::FrozenAccount);
+        }
+        self.validate_capacity(target)?;
+
+        Ok((amount, target))
+    }
+
+    fn validate_unlock(&self, record: &TransactionRecord) -> Result<()> {
+        if !self.account_ledger.contains(&record.client) {
+            return Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx });
+        }
+        Ok(())
+    }
+
+    /// Returns the Account to be closed.
+    fn validate_close(&self, record: &TransactionRecord) -> Result<&Account> {
+        match self.account_ledger.get(&record.client) {
+            Some(account) => {
+                if account.available() != 0.00 || account.held() != 0.00 || account.total() != 0.00 {
+                    return Err(TransactionError::NonZeroBalance);
+                }
+                Ok(account)
+            }
+            None => Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx })
+        }
+    }
+
+    /// Returns the signed amount to adjust the Account by.
+    fn validate_adjustment(&self, record: &TransactionRecord) -> Result<f32> {
+        let amount = match record.amount {
+            Some(amount) => amount,
+            None => return Err(TransactionError::MissingAmount),
+        };
+
+        match self.account_ledger.get(&record.client) {
+            Some(account) => {
+                if self.falls_short(account.total() + amount, 0.00) {
+                    return Err(TransactionError::NegativeTotal);
+                }
+            }
+            None => {
+                return Err(TransactionError::InvalidAccount { client: record.client, tx: record.tx });
+            }
+        };
+
+        Ok(amount)
+    }
+
+    /// Common code pulled for Resolve and both kinds of Chargeback. The only
+    /// difference between them is how the accounts are changed in the end.
+    fn process_dispute(&mut self, record: &TransactionRecord) -> Result<AccountSnapshot> {
+        let amount = self.validate_dispute_settlement(record)?;
+
+        if let Some(account) = self.account_ledger.get(&record.client) {
+            let updated_account = self.update_dispute(account, amount, &record._type)?;
+            self.account_ledger.insert(record.client, updated_account);
+        }
+        let state = match record._type {
+            TransactionType::CHARGEBACK | TransactionType::SOFT_CHARGEBACK => {
+                if let Some(t_entry) = self.transaction_ledger.get_mut(&record.tx) {
+                    t_entry.charged_back += amount;
+                }
+                DisputeState::ChargedBack
+            }
+            _ => DisputeState::Resolved(amount),
+        };
+        self.dispute_ledger.insert(record.tx, state);
+
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    /// Only deposits and withdrawals are tracked, since replaying them
+    /// would move money twice. A record is a replay when the same client
+    /// already made a deposit or withdrawal with its id. Evicted transactions
+    /// which are still remembered as expired count as applied.
+    fn is_replay(&self, record: &TransactionRecord) -> bool {
+        let tracked = matches!(record._type, TransactionType::DEPOSIT | TransactionType::WITHDRAWAL);
+        let applied = self.expired_transactions.contains(&record.tx) || self.applied_records.contains(&(record.client, record.tx));
+        self.settings.idempotent && tracked && applied
+    }
+
+    /// Records an alert when the held amount of the client's Account
+    /// crosses the configured threshold. Staying above it doesn't raise
+    /// another alert until the held amount drops back below it.
+    fn check_held_threshold(&mut self, client: u16, held_before: f32, held: f32) {
+        if let Some(threshold) = self.settings.held_alert_threshold {
+            if held_before <= threshold && held > threshold {
+                self.audit_log.push(AuditEvent::HeldThresholdExceeded { client, held });
+            }
+        }
+    }
+
+    /// Appends the current balances of the client's Account to their history.
+    fn record_balance(&mut self, client: u16, tx: u32) {
+        if let Some(account) = self.account_ledger.get(&client) {
+            let point = BalancePoint { tx, available: account.available(), held: account.held(), total: account.total() };
+            self.balance_history.entry(client).or_default().push(point);
+        }
+    }
+
+    /// Catches handlers that leave an Account unbalanced in debug builds.
+    fn debug_assert_invariants(&self, client: u16) {
+        if let Some(account) = self.account_ledger.get(&client) {
+            debug_assert!(account.is_balanced(), "Account {} is unbalanced after the last operation", client);
+        }
+    }
+
+    /// Captures the current state of the given client's Account
+    /// after transaction `tx` was applied to it.
+    fn account_snapshot(&self, client: u16, tx: u32) -> Result<AccountSnapshot> {
+        match self.account_ledger.get(&client) {
+            Some(account) => Ok(AccountSnapshot::from(account)),
+            None => Err(TransactionError::InvalidAccount { client, tx })
+        }
+    }
+
+    /// Disputed amounts are signed like the transaction they belong to,
+    /// with withdrawals being negative. The held amount is always positive.
+    fn update_dispute(&self, account: &Account, amount: f32, _type: &TransactionType) -> Result<Account> {
+        let withdrawal = amount < 0.00;
+        match _type {
+            TransactionType::RESOLVE if withdrawal => account.resolve_withdrawal(-amount).checked(),
+            TransactionType::RESOLVE => account.try_resolve(amount),
+            TransactionType::CHARGEBACK if withdrawal => account.chargeback_withdrawal(-amount).checked(),
+            TransactionType::CHARGEBACK => account.try_chargeback(amount),
+            TransactionType::SOFT_CHARGEBACK if withdrawal => account.soft_chargeback_withdrawal(-amount).checked(),
+            TransactionType::SOFT_CHARGEBACK => account.try_soft_chargeback(amount),
+            _ => Err(TransactionError::InvalidOperation)
+        }
+    }
+
+    /// Holds the signed disputed amount, see `update_dispute`.
+    fn hold_disputed(account: &Account, amount: f32) -> Result<Account> {
+        if amount < 0.00 {
+            account.dispute_withdrawal(-amount).checked()
+        } else {
+            account.try_dispute(amount)
+        }
+    }
+}
+
+impl TransactionService {
+    /// Starts configuring a service with optional behaviours.
+    pub fn builder() -> TransactionServiceBuilder {
+        TransactionServiceBuilder::new()
+    }
+
+    /// Rebuilds an in-memory service from a previously captured `EngineSnapshot`,
+    /// with the settings it was captured with. Stats are not part of the snapshot
+    /// and start from zero, and hooks and risk checks have to be registered again.
+    pub fn restore(snapshot: EngineSnapshot) -> Self {
+        let ledger_order = snapshot.history.iter()
+            .filter(|tx| snapshot.transaction_ledger.contains_key(tx))
+            .copied()
+            .collect();
+        TransactionService {
+            account_ledger: snapshot.account_ledger,
+            transaction_ledger: snapshot.transaction_ledger,
+            adjustment_ledger: snapshot.adjustment_ledger,
+            transfer_ledger: snapshot.transfer_ledger,
+            accrued_interest: snapshot.accrued_interest,
+            holds: snapshot.holds,
+            dispute_ledger: snapshot.dispute_ledger,
+            overdraft_limits: snapshot.overdraft_limits,
+            client_transactions: snapshot.client_transactions,
+            history: snapshot.history,
+            frozen_accounts: snapshot.frozen_accounts.into_iter().collect(),
+            applied_records: snapshot.applied_records.into_iter().collect(),
+            ledger_order,
+            expired_transactions: snapshot.expired_transactions.iter().copied().collect(),
+            expired_order: snapshot.expired_transactions.into_iter().collect(),
+            expired_totals: snapshot.expired_totals,
+            settings: snapshot.settings,
+            ..Default::default()
+        }
+    }
+
+    /// Rebuilds a service by applying the events of an audit log in order.
+    /// Events that are only a consequence of other events, such as alerts,
+    /// are skipped. The events are applied with the default behaviours, so
+    /// the log of a service with e.g. withdrawal fees won't replay identically.
+    pub fn replay(events: &[AuditEvent]) -> Result<Self> {
+        let mut service: Self = Default::default();
+        for event in events {
+            match event {
+                AuditEvent::Transaction { _type, client, tx, amount, target, currency, reference, timestamp, .. } => {
+                    service.process(TransactionRecord {
+                        _type: _type.clone(),
+                        client: *client,
+                        tx: *tx,
+                        amount: *amount,
+                        target_client: *target,
+                        timestamp: *timestamp,
+                        currency: currency.clone(),
+                        reference: reference.clone(),
+                    })?;
+                }
+                AuditEvent::InterestAccrued { client, rate, .. } => {
+                    service.accrue_interest(*client, *rate)?;
+                }
+                AuditEvent::Undone { .. } => {
+                    service.undo_last()?;
+                }
+                AuditEvent::HoldPlaced { client, tx, amount, .. } => {
+                    service.place_hold(*client, *tx, *amount)?;
+                }
+                AuditEvent::HoldReleased { client, tx, .. } => {
+                    service.release_hold(*client, *tx)?;
+                }
+                AuditEvent::HoldCaptured { client, tx, .. } => {
+                    service.capture_hold(*client, *tx)?;
+                }
+                AuditEvent::HeldThresholdExceeded { .. } | AuditEvent::AccountLocked { .. } => {}
+            }
+        }
+        Ok(service)
+    }
+
+    /// Processes the records on `shards` threads and merges the results into a
+    /// new service with the default behaviours. Records are sharded by client,
+    /// which keeps the order of every client's records and therefore gives the
+    /// same balances as processing them one after the other. Since transfers
+    /// move money between clients, records containing a transfer are processed
+    /// on a single thread instead. No more threads are spawned than there are
+    /// clients or than the machine can run in parallel. The merged audit log is
+    /// grouped by shard, whereas the history keeps the order of the records.
+    pub fn process_parallel(records: Vec<TransactionRecord>, shards: usize) -> Self {
+        let clients = records.iter().map(|record| record.client).collect::<HashSet<u16>>().len();
+        let parallelism = std::thread::available_parallelism().map_or(1, |parallelism| parallelism.get());
+        let shards = shards.min(clients).min(parallelism).max(1);
+        if shards == 1 || !Self::shardable(&records) {
+            let mut service: Self = Default::default();
+            service.process_all(records);
+            return service;
+        }
+
+        let mut positions: HashMap<u32, usize> = HashMap::new();
+        for (position, record) in records.iter().enumerate() {
+            if matches!(record._type, TransactionType::DEPOSIT | TransactionType::WITHDRAWAL) {
+                positions.entry(record.tx).or_insert(position);
+            }
+        }
+        let mut partitions: Vec<Vec<TransactionRecord>> = (0..shards).map(|_| Vec::new()).collect();
+        for record in records {
+            partitions[record.client as usize % shards].push(record);
+        }
+        let processed: Vec<Self> = std::thread::scope(|scope| {
+            let handles: Vec<_> = partitions.into_iter()
+                .filter(|partition| !partition.is_empty())
+                .map(|partition| scope.spawn(move || {
+                    let mut service: Self = Default::default();
+                    service.process_all(partition);
+                    service
+                }))
+                .collect();
+            handles.into_iter()
+                .map(|handle| handle.join().expect("a shard panicked while processing"))
+                .collect()
+        });
+
+        let mut service: Self = Default::default();
+        for shard in processed {
+            service.merge(shard).expect("shards have disjoint clients and transactions");
+        }
+        service.history.sort_by_key(|tx| positions.get(tx).copied());
+        service
+    }
+
+    /// Checks whether the records can be split by client, that is when no
+    /// record moves money between clients and no two clients record a
+    /// transaction under the same id.
+    fn shardable(records: &[TransactionRecord]) -> bool {
+        let mut owners: HashMap<u32, u16> = HashMap::new();
+        records.iter().all(|record| match record._type {
+            TransactionType::TRANSFER => false,
+            TransactionType::DEPOSIT | TransactionType::WITHDRAWAL | TransactionType::ADJUSTMENT =>
+                *owners.entry(record.tx).or_insert(record.client) == record.client,
+            _ => true,
+        })
+    }
+
+    /// Moves the state of another service into this one. The services must
+    /// keep track of disjoint sets of clients and transactions: a client with
+    /// an Account in both fails with `AccountAlreadyExists`, and a transaction,
+    /// adjustment, transfer or hold id known to both fails with
+    /// `DuplicateTransaction`. Overdraft limits are merged as well, and a client
+    /// with a different limit in each fails with `ConflictingOverdraftLimit`.
+    /// Nothing is merged when any of the checks fails.
+    /// Stats are added up, the audit log and history of `other` are appended,
+    /// and the settings, hooks and risk checks of this service are kept.
+    pub fn merge(&mut self, other: TransactionService) -> Result<()> {
+        if let Some(client) = other.account_ledger.keys().find(|client| self.account_ledger.contains_key(client)) {
+            return Err(TransactionError::AccountAlreadyExists(*client));
+        }
+        let duplicate = other.transaction_ledger.keys().find(|tx| self.transaction_ledger.contains_key(tx))
+            .or_else(|| other.adjustment_ledger.keys().find(|tx| self.adjustment_ledger.contains_key(tx)))
+            .or_else(|| other.transfer_ledger.keys().find(|tx| self.transfer_ledger.contains_key(tx)))
+            .or_else(|| other.holds.keys().find(|tx| self.holds.contains_key(tx)));
+        if let Some(tx) = duplicate {
+            return Err(TransactionError::DuplicateTransaction(*tx));
+        }
+        let conflicting = other.overdraft_limits.iter()
+            .find(|(client, limit)| self.overdraft_limits.get(client).is_some_and(|own| own != *limit));
+        if let Some((client, _)) = conflicting {
+            return Err(TransactionError::ConflictingOverdraftLimit(*client));
+        }
+
+        self.account_ledger.extend(other.account_ledger);
+        self.transaction_ledger.extend(other.transaction_ledger);
+        self.adjustment_ledger.extend(other.adjustment_ledger);
+        self.transfer_ledger.extend(other.transfer_ledger);
+        self.accrued_interest.extend(other.accrued_interest);
+        self.holds.extend(other.holds);
+        self.dispute_ledger.extend(other.dispute_ledger);
+        self.overdraft_limits.extend(other.overdraft_limits);
+        self.frozen_accounts.extend(other.frozen_accounts);
+        self.client_transactions.extend(other.client_transactions);
+        self.history.extend(other.history);
+        self.ledger_order.extend(other.ledger_order);
+        self.expired_transactions.extend(other.expired_transactions);
+        self.expired_order.extend(other.expired_order);
+        self.expired_totals.extend(other.expired_totals);
+        self.applied_records.extend(other.applied_records);
+        self.balance_history.extend(other.balance_history);
+        self.stats.merge(other.stats);
+        self.audit_log.extend(other.audit_log);
+        Ok(())
+    }
+
+    /// Rebuilds a service from the JSON produced by `to_json`.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        Ok(Self::restore(serde_json::from_str(json)?))
+    }
+
+    /// Rebuilds a service from the bytes produced by `to_bincode`.
+    pub fn from_bincode(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::restore(bincode::deserialize(bytes)?))
+    }
+}
+
+/// Checks that the amount written as `text` has at most `scale` decimals.
+/// Trailing zeros don't count. Readers check amounts on the text they were
+/// written with, since the parsed `f32` no longer knows its decimals.
+pub(crate) fn validate_decimals(text: &str, scale: u32) -> Result<()> {
+    let decimals = text.split_once('.')
+        .map_or(0, |(_, fraction)| fraction.trim_end_matches('0').len());
+    if decimals > scale as usize {
+        return Err(TransactionError::AmountExceedsScale);
+    }
+    Ok(())
+}
+
+/// Each operation runs the checks specific to its type, the same ones
+/// `TransactionService::validate` runs for it, before touching any of the ledgers.
+/// The checks shared by every type, such as the lock, freeze, scale, range and
+/// currency checks, only run in `process`, so calling these methods directly
+/// skips them.
+impl<S: AccountStore> Transaction<TransactionRecord> for TransactionService<S> {
+    fn deposit(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let amount = self.validate_deposit(&record)?;
+
+        match self.account_ledger.get(&record.client) {
+            Some(account) => {
+                let updated_account = account.try_deposit(amount)?.tagged(record.currency.as_deref());
+                self.account_ledger.insert(record.client, updated_account);
+            }
+            None => {
+                let account = Account::new(record.client).tagged(record.currency.as_deref()).try_deposit(amount)?;
+                self.account_ledger.insert(record.client, account);
+            }
+        };
+        self.transaction_ledger.insert(record.tx, TransactionEntry { client: record.client, amount, charged_back: 0.00, refunded: 0.00, timestamp: record.timestamp, reference: record.reference.clone() });
+        self.client_transactions.entry(record.client).or_default().push(record.tx);
+        self.history.push(record.tx);
+        self.evict_expired(record.tx);
+
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    /// The fee is moved to the fee collection Account like a transfer,
+    /// so disputing the withdrawal later doesn't refund the fee.
+    fn withdrawal(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let (amount, fee) = self.validate_withdrawal(&record)?;
+
+        let updated_account = match self.account_ledger.get(&record.client) {
+            Some(account) => account.try_withdrawal(amount + fee)?,
+            // Only reachable with `MissingAccountPolicy::TreatAsEmpty` for a client who may overdraw.
+            None => Account::new(record.client).tagged(record.currency.as_deref()).try_withdrawal(amount + fee)?,
+        };
+        let fee_client = self.settings.fee_client;
+        let fee_account = match self.account_ledger.get(&fee_client) {
+            _ if fee == 0.00 => None,
+            Some(account) => Some(account.try_deposit(fee)?),
+            None => Some(Account::new(fee_client).try_deposit(fee)?),
+        };
+        self.account_ledger.insert(record.client, updated_account);
+        self.transaction_ledger.insert(record.tx, TransactionEntry { client: record.client, amount: -amount, charged_back: 0.00, refunded: 0.00, timestamp: record.timestamp, reference: record.reference.clone() });
+        self.client_transactions.entry(record.client).or_default().push(record.tx);
+        self.history.push(record.tx);
+        self.evict_expired(record.tx);
+
+        if let Some(fee_account) = fee_account {
+            self.account_ledger.insert(fee_client, fee_account);
+            self.transfer_ledger.insert(record.tx, TransferEntry { client: record.client, target: fee_client, amount: fee });
+        }
+
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    fn dispute(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let amount = self.validate_dispute(&record)?;
+
+        if let Some(account) = self.account_ledger.get(&record.client) {
+            let updated_account = Self::hold_disputed(account, amount)?;
+            self.account_ledger.insert(record.client, updated_account);
+        }
+        self.dispute_ledger.insert(record.tx, DisputeState::Disputed(amount));
+
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    fn resolve(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        self.process_dispute(&record)
+    }
+
+    fn chargeback(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        self.process_dispute(&record)
+    }
+
+    fn soft_chargeback(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        self.process_dispute(&record)
+    }
+
+    fn reopen(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let amount = self.validate_reopen(&record)?;
+
+        if let Some(account) = self.account_ledger.get(&record.client) {
+            let updated_account = Self::hold_disputed(account, amount)?;
+            self.account_ledger.insert(record.client, updated_account);
+        }
+        self.dispute_ledger.insert(record.tx, DisputeState::Disputed(amount));
+
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    /// Nothing of the transaction is charged back anymore after the appeal,
+    /// but the transaction is final and can't be disputed again.
+    fn appeal(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let amount = self.validate_appeal(&record)?;
+
+        if let Some(account) = self.account_ledger.get(&record.client) {
+            let updated_account = account.appeal(amount).checked()?;
+            self.account_ledger.insert(record.client, updated_account);
+        }
+        if let Some(t_entry) = self.transaction_ledger.get_mut(&record.tx) {
+            t_entry.charged_back = 0.00;
+        }
+        self.dispute_ledger.insert(record.tx, DisputeState::Appealed);
+
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    /// The refund is recorded on the withdrawal, so that it can't be
+    /// refunded twice and disputes only cover what wasn't refunded yet.
+    fn refund(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let amount = self.validate_refund(&record)?;
+
+        if let Some(account) = self.account_ledger.get(&record.client) {
+            let updated_account = account.try_deposit(amount)?;
+            self.account_ledger.insert(record.client, updated_account);
+        }
+        if let Some(t_entry) = self.transaction_ledger.get_mut(&record.tx) {
+            t_entry.refunded += amount;
+        }
+
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    /// All checks are done before either account is touched so that
+    /// a failed transfer never leaves a partial debit behind.
+    fn transfer(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let (amount, target) = self.validate_transfer(&record)?;
+
+        let currency = record.currency.clone()
+            .or_else(|| self.account_ledger.get(&record.client).and_then(|acc| acc.currency().map(String::from)));
+        let source_account = match self.account_ledger.get(&record.client) {
+            Some(account) => Some(account.try_withdrawal(amount)?),
+            None => None,
+        };
+        let target_account = match self.account_ledger.get(&target) {
+            Some(account) => account.try_deposit(amount)?.tagged(currency.as_deref()),
+            None => Account::new(target).tagged(currency.as_deref()).try_deposit(amount)?,
+        };
+
+        if let Some(source_account) = source_account {
+            self.account_ledger.insert(record.client, source_account);
+        }
+        self.account_ledger.insert(target, target_account);
+
+        self.transfer_ledger.insert(record.tx, TransferEntry { client: record.client, target, amount });
+
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    /// Reinstates a locked Account. Unlocking an Account which
+    /// is not locked has no effect.
+    fn unlock(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        self.validate_unlock(&record)?;
+
+        if let Some(account) = self.account_ledger.get(&record.client) {
+            let updated_account = account.unlock();
+            self.account_ledger.insert(record.client, updated_account);
+        }
+        self.account_snapshot(record.client, record.tx)
+    }
+
+    /// Returns the final state of the Account. Since the Account is removed,
+    /// along with its deposits, withdrawals and overdraft limit, a later
+    /// deposit for the same client opens a brand new Account.
+    fn close(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let snapshot = AccountSnapshot::from(self.validate_close(&record)?);
+        self.account_ledger.remove(&record.client);
+        self.forget_transactions(record.client);
+        self.overdraft_limits.remove(&record.client);
+
+        Ok(snapshot)
+    }
+
+    /// Unlike deposits and withdrawals, adjustments are kept in their own
+    /// ledger for auditing and can't be disputed.
+    fn adjustment(&mut self, record: TransactionRecord) -> Result<AccountSnapshot> {
+        let amount = self.validate_adjustment(&record)?;
+
+        if let Some(account) = self.account_ledger.get(&record.client) {
+            let updated_account = account.try_adjust(amount)?;
+            self.account_ledger.insert(record.client, updated_account);
+            self.adjustment_ledger.insert(record.tx, TransactionEntry { client: record.client, amount, charged_back: 0.00, refunded: 0.00, timestamp: record.timestamp, reference: record.reference.clone() });
+        }
+
+        self.account_snapshot(record.client, record.tx)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionTypeStats;
+
+    /// Builds a record without a target client, timestamp, currency or reference.
+    fn record(_type: TransactionType, client: u16, tx: u32, amount: Option<f32>) -> TransactionRecord {
+        record_to(_type, client, tx, amount, None)
+    }
+
+    /// Builds a record like `record` which moves money to `target_client`.
+    fn record_to(_type: TransactionType, client: u16, tx: u32, amount: Option<f32>, target_client: Option<u16>) -> TransactionRecord {
+        TransactionRecord {
+            _type,
+            client,
+            tx,
+            amount,
+            target_client,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        }
+    }
+
+    #[test]
+    fn should_be_able_to_deposit_funds() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.5),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let result1 = service.process(record1);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.5, held: 0.0, total: 1.5, locked: false }), result1);
+        assert_eq!(1.5, service.account_ledger.get(&1).unwrap().available());
+        assert_eq!(1.5, service.account_ledger.get(&1).unwrap().total());
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(3.0),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+
+        let result2 = service.process(record2);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 4.5, held: 0.0, total: 4.5, locked: false }), result2);
+        assert_eq!(4.5, service.account_ledger.get(&1).unwrap().available());
+        assert_eq!(4.5, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_be_able_to_withdraw_from_account_with_funds() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let result1 = service.process(record1);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.5, held: 0.0, total: 1.5, locked: false }), result1);
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().available());
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().total());
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 1,
+            amount: Some(1.40),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+
+        let result2 = service.process(record2);
+        assert_eq!(Ok("0.1000".to_string()), result2.map(|acc| format!("{:.4}", acc.available)));
+        assert_eq!("0.1000", format!("{:.4}", service.account_ledger.get(&1).unwrap().available()));
+        assert_eq!("0.1000", format!("{:.4}", service.account_ledger.get(&1).unwrap().total()));
+    }
+
+    #[test]
+    fn should_error_out_if_funds_are_insufficient() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let result1 = service.process(record1);
+
+        assert_eq!(Err(TransactionError::InvalidAccount { client: 1, tx: 1 }), result1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.40),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let result3 = service.process(record3);
+
+        assert_eq!(Err(TransactionError::InsufficientFunds), result3);
+        assert_eq!(1.40, service.account_ledger.get(&1).unwrap().total());
+        assert_eq!(1.40, service.account_ledger.get(&1).unwrap().available());
+    }
+
+    #[test]
+    fn should_not_raise_dispute_for_invalid_transactions() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 2,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+        assert_eq!(Err(TransactionError::MissingTransaction), result);
+    }
+
+    #[test]
+    fn should_raise_dispute_for_valid_transaction() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.0, held: 1.5, total: 1.5, locked: false }), result);
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(0.00, acc.available());
+        assert_eq!(1.50, acc.total());
+        assert_eq!(1.50, acc.held());
+    }
+
+    #[test]
+    fn should_resolve_a_valid_dispute() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::RESOLVE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record3);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.5, held: 0.0, total: 1.5, locked: false }), result);
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(1.50, acc.available());
+        assert_eq!(1.50, acc.total());
+        assert_eq!(0.00, acc.held());
+    }
+
+    #[test]
+    fn should_not_be_able_raise_a_dispute_if_funds_are_insufficient() {
+        let mut service: TransactionService = Default::default();
+
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.40),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 2,
+            amount: Some(1.40),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let result = service.process(record3);
+
+        assert_eq!(Err(TransactionError::InsufficientFunds), result);
+    }
+
+    #[test]
+    fn should_reverse_transaction_for_a_valid_chargeback() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::CHARGEBACK,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record3);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.0, held: 0.0, total: 0.0, locked: true }), result);
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(0.00, acc.available());
+        assert_eq!(0.00, acc.total());
+        assert_eq!(0.00, acc.held());
+        assert!(acc.locked());
+    }
+
+    #[test]
+    fn should_not_resolve_a_transaction_which_is_not_in_dispute() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::RESOLVE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Err(TransactionError::TransactionNotDisputed), result);
+    }
+
+    #[test]
+    fn should_not_process_transaction_for_a_locked_account() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::CHARGEBACK,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record4 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record4);
+
+        assert_eq!(Err(TransactionError::LockedAccount), result);
+    }
+
+    #[test]
+    fn should_transfer_funds_between_clients() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::TRANSFER,
+            client: 1,
+            tx: 2,
+            amount: Some(1.00),
+            target_client: Some(2),
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.5, held: 0.0, total: 0.5, locked: false }), result);
+        let source = service.account_ledger.get(&1).unwrap();
+        assert_eq!(0.50, source.available());
+        assert_eq!(0.50, source.total());
+        let target = service.account_ledger.get(&2).unwrap();
+        assert_eq!(1.00, target.available());
+        assert_eq!(1.00, target.total());
+    }
+
+    #[test]
+    fn should_not_transfer_if_funds_are_insufficient() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::TRANSFER,
+            client: 1,
+            tx: 2,
+            amount: Some(2.00),
+            target_client: Some(2),
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Err(TransactionError::InsufficientFunds), result);
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().available());
+        assert!(!service.account_ledger.contains_key(&2));
+    }
+
+    #[test]
+    fn should_not_transfer_without_a_target_client() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::TRANSFER,
+            client: 1,
+            tx: 2,
+            amount: Some(1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Err(TransactionError::MissingTargetClient), result);
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().available());
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_not_transfer_amounts_of_zero_or_below() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 2, 2, Some(10.00)));
+
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record_to(TransactionType::TRANSFER, 1, 3, Some(-10.00), Some(2))));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record_to(TransactionType::TRANSFER, 1, 4, Some(0.00), Some(2))));
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().total());
+        assert_eq!(10.00, service.account_ledger.get(&2).unwrap().total());
+        assert!(service.transfer_ledger.is_empty());
+    }
+
+    #[test]
+    fn should_not_deposit_or_withdraw_amounts_of_zero_or_below() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::DEPOSIT, 1, 2, Some(-100.00))));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::DEPOSIT, 1, 3, Some(0.00))));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::WITHDRAWAL, 1, 4, Some(-1.00))));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::WITHDRAWAL, 1, 5, Some(-0.00))));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::DEPOSIT, 2, 6, Some(-0.00))));
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().total());
+        assert_eq!(vec![1], service.client_transactions(1));
+        assert_eq!(1, service.accounts().count());
+    }
+
+    #[test]
+    fn should_not_transfer_to_the_same_client() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+
+        let result = service.process(record_to(TransactionType::TRANSFER, 1, 2, Some(1.00), Some(1)));
+
+        assert_eq!(Err(TransactionError::SelfTransfer), result);
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().available());
+        assert!(service.transfer_ledger.is_empty());
+    }
+
+    #[test]
+    fn should_iterate_over_all_accounts() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 2,
+            tx: 2,
+            amount: Some(2.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let count = service.accounts().count();
+        let total: f32 = service.accounts().map(|(_, acc)| acc.total()).sum();
+
+        assert_eq!(2, count);
+        assert_eq!(3.50, total);
+    }
+
+    #[test]
+    fn should_be_able_to_deposit_after_unlocking_a_locked_account() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::CHARGEBACK,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record3);
+        assert!(service.account_ledger.get(&1).unwrap().locked());
+
+        let record4 = TransactionRecord {
+            _type: TransactionType::UNLOCK,
+            client: 1,
+            tx: 2,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record4);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.0, held: 0.0, total: 0.0, locked: false }), result);
+        assert!(!service.account_ledger.get(&1).unwrap().locked());
+
+        let record5 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 3,
+            amount: Some(2.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record5);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 2.0, held: 0.0, total: 2.0, locked: false }), result);
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(2.00, acc.available());
+        assert_eq!(2.00, acc.total());
+    }
+
+    #[test]
+    fn should_not_unlock_a_missing_account() {
+        let mut service: TransactionService = Default::default();
+        let record = TransactionRecord {
+            _type: TransactionType::UNLOCK,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record);
+
+        assert_eq!(Err(TransactionError::InvalidAccount { client: 1, tx: 1 }), result);
+    }
+
+    #[test]
+    fn should_report_the_outcome_of_every_record_in_a_batch() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 1,
+                amount: Some(1.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::WITHDRAWAL,
+                client: 1,
+                tx: 2,
+                amount: Some(3.00),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 3,
+                amount: Some(0.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+        ];
+
+        let outcomes = service.process_all(records);
+
+        assert_eq!(vec![
+            (1, Ok(AccountSnapshot { client: 1, available: 1.50, held: 0.0, total: 1.50, locked: false })),
+            (2, Err(TransactionError::InsufficientFunds)),
+            (3, Ok(AccountSnapshot { client: 1, available: 2.00, held: 0.0, total: 2.00, locked: false })),
+        ], outcomes);
+    }
+
+    #[test]
+    fn should_continue_processing_after_restoring_a_snapshot() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let json = serde_json::to_string(&service.snapshot()).unwrap();
+        let snapshot: EngineSnapshot = serde_json::from_str(&json).unwrap();
+        let mut restored = TransactionService::restore(snapshot);
+
+        let expected = service.process(record(TransactionType::RESOLVE, 1, 1, None));
+        let result = restored.process(record(TransactionType::RESOLVE, 1, 1, None));
+
+        assert_eq!(expected, result);
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.50, held: 0.0, total: 1.50, locked: false }), result);
+        assert_eq!(Err(TransactionError::TransactionNotDisputed), restored.process(record(TransactionType::RESOLVE, 1, 1, None)));
+    }
+
+    #[test]
+    fn should_withdraw_within_the_overdraft_limit() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        assert_eq!(Err(TransactionError::InsufficientFunds), service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(1.50))));
+
+        service.set_overdraft_limit(1, 0.50);
+        let result = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(1.50)));
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: -0.50, held: 0.0, total: -0.50, locked: false }), result);
+    }
+
+    #[test]
+    fn should_not_withdraw_beyond_the_overdraft_limit() {
+        let mut service: TransactionService = Default::default();
+        service.set_overdraft_limit(1, 0.50);
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 2,
+            amount: Some(1.75),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Err(TransactionError::InsufficientFunds), result);
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().available());
+    }
+
+    #[test]
+    fn should_report_the_client_and_tx_of_a_missing_account() {
+        let mut service: TransactionService = Default::default();
+        let record = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 7,
+            tx: 42,
+            amount: Some(1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record);
+
+        match result {
+            Err(TransactionError::InvalidAccount { client, tx }) => {
+                assert_eq!(7, client);
+                assert_eq!(42, tx);
+            }
+            _ => panic!("expected an InvalidAccount error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn should_validate_records_without_changing_any_balances() {
+        let mut service: TransactionService = Default::default();
+        let deposit = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        assert_eq!(Ok(()), service.validate(&deposit));
+        assert!(service.account_ledger.is_empty());
+        let _ = service.process(deposit);
+
+        let invalid_records = vec![
+            TransactionRecord {
+                _type: TransactionType::WITHDRAWAL,
+                client: 1,
+                tx: 2,
+                amount: Some(3.00),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 3,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DISPUTE,
+                client: 1,
+                tx: 4,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::RESOLVE,
+                client: 1,
+                tx: 1,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+        ];
+
+        for record in invalid_records {
+            let validation = service.validate(&record);
+            assert!(validation.is_err());
+
+            let acc = service.account_ledger.get(&1).unwrap();
+            assert_eq!(1.50, acc.available());
+            assert_eq!(1.50, acc.total());
+            assert_eq!(validation, service.process(record).map(|_| ()));
+        }
+    }
+
+    #[test]
+    fn should_validate_a_locked_account_the_same_way_as_process() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+        let _ = service.process(TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+        let _ = service.process(TransactionRecord {
+            _type: TransactionType::CHARGEBACK,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+
+        let deposit = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 2,
+            amount: Some(1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        assert_eq!(Err(TransactionError::LockedAccount), service.validate(&deposit));
+        assert_eq!(Err(TransactionError::LockedAccount), service.process(deposit));
+    }
+
+    #[test]
+    fn should_not_settle_a_dispute_for_more_than_the_held_amount() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        // Simulate a corrupted ledger where less than the disputed amount is held.
+        let corrupted = Account::from_parts(1, 0.00, 0.50, 0.50, false);
+        service.account_ledger.insert(1, corrupted);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::RESOLVE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record3);
+
+        assert_eq!(Err(TransactionError::InsufficientHeldFunds), result);
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(0.50, acc.held());
+        assert_eq!(0.00, acc.available());
+        assert_eq!(Some(&DisputeState::Disputed(1.50)), service.dispute_ledger.get(&1));
+    }
+
+    #[test]
+    fn should_count_the_outcome_of_every_processed_transaction() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 1,
+                amount: Some(1.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 2,
+                tx: 2,
+                amount: Some(2.00),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::WITHDRAWAL,
+                client: 1,
+                tx: 3,
+                amount: Some(5.00),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DISPUTE,
+                client: 2,
+                tx: 2,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+        ];
+        let _ = service.process_all(records);
+
+        let stats = service.stats();
+
+        assert_eq!(4, stats.processed);
+        assert_eq!(3, stats.succeeded);
+        assert_eq!(1, stats.failed);
+        assert_eq!(Some(&TransactionTypeStats { succeeded: 2, failed: 0 }), stats.by_type.get(&TransactionType::DEPOSIT));
+        assert_eq!(Some(&TransactionTypeStats { succeeded: 0, failed: 1 }), stats.by_type.get(&TransactionType::WITHDRAWAL));
+        assert_eq!(Some(&TransactionTypeStats { succeeded: 1, failed: 0 }), stats.by_type.get(&TransactionType::DISPUTE));
+        assert_eq!(None, stats.by_type.get(&TransactionType::CHARGEBACK));
+    }
+
+    #[test]
+    fn should_close_an_account_with_zero_balance() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 2,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::CLOSE,
+            client: 1,
+            tx: 3,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record3);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.00, held: 0.00, total: 0.00, locked: false }), result);
+        assert!(!service.account_ledger.contains_key(&1));
+    }
+
+    #[test]
+    fn should_not_close_an_account_with_funds() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::CLOSE,
+            client: 1,
+            tx: 2,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Err(TransactionError::NonZeroBalance), result);
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_reopen_a_closed_account_with_a_deposit() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.open_account(1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::CLOSE,
+            client: 1,
+            tx: 2,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 3,
+            amount: Some(2.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record3);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 2.00, held: 0.00, total: 2.00, locked: false }), result);
+    }
+
+    #[test]
+    fn should_not_dispute_transactions_from_before_a_close() {
+        let mut service: TransactionService = Default::default();
+        service.set_overdraft_limit(1, 10.00);
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(5.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(5.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 2, None));
+        let _ = service.process(record(TransactionType::RESOLVE, 1, 2, None));
+        assert!(service.process(record(TransactionType::CLOSE, 1, 3, None)).is_ok());
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 4, Some(3.00)));
+
+        assert_eq!(Err(TransactionError::MissingTransaction), service.process(record(TransactionType::DISPUTE, 1, 1, None)));
+        assert_eq!(Err(TransactionError::TransactionNotDisputed), service.process(record(TransactionType::REOPEN, 1, 2, None)));
+        assert_eq!(Err(TransactionError::InsufficientFunds), service.process(record(TransactionType::WITHDRAWAL, 1, 5, Some(4.00))));
+        let account = service.account_ledger.get(&1).unwrap();
+        assert_eq!(3.00, account.available());
+        assert_eq!(0.00, account.held());
+        assert_eq!(vec![4], service.client_transactions(1));
+        assert_eq!(vec![4], service.history);
+        assert_eq!(Ok(()), service.reconcile());
+    }
+
+    #[test]
+    fn should_write_report_rows_in_ascending_client_order() {
+        let mut service: TransactionService = Default::default();
+        for (tx, client) in [(1, 3), (2, 1), (3, 2)] {
+            let record = TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client,
+                tx,
+                amount: Some(1.00),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            };
+            let _ = service.process(record);
+        }
+
+        let mut report = Vec::new();
+        service.write_report(&mut report).unwrap();
+
+        assert_eq!("client,available,held,total,locked\n\
+                    1,1.0000,0.0000,1.0000,false\n\
+                    2,1.0000,0.0000,1.0000,false\n\
+                    3,1.0000,0.0000,1.0000,false\n", String::from_utf8(report).unwrap());
+    }
+
+    #[test]
+    fn should_apply_a_positive_adjustment() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::ADJUSTMENT,
+            client: 1,
+            tx: 2,
+            amount: Some(0.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 2.00, held: 0.00, total: 2.00, locked: false }), result);
+        assert_eq!(0.50, service.adjustment_ledger.get(&2).unwrap().amount);
+        assert!(!service.transaction_ledger.contains_key(&2));
+    }
+
+    #[test]
+    fn should_apply_a_negative_adjustment() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::ADJUSTMENT,
+            client: 1,
+            tx: 2,
+            amount: Some(-1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.50, held: 0.00, total: 0.50, locked: false }), result);
+        assert_eq!(-1.00, service.adjustment_ledger.get(&2).unwrap().amount);
+    }
+
+    #[test]
+    fn should_not_adjust_the_total_below_zero() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::ADJUSTMENT,
+            client: 1,
+            tx: 2,
+            amount: Some(-2.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Err(TransactionError::NegativeTotal), result);
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().total());
+        assert!(service.adjustment_ledger.is_empty());
+    }
+
+    /// Minimal store used to prove that the service works with any `AccountStore`.
+    #[derive(Default)]
+    struct VecStore(Vec<(u16, Account)>);
+
+    impl AccountStore for VecStore {
+        fn get(&self, client: &u16) -> Option<&Account> {
+            self.0.iter()
+                .find(|(c, _)| c == client)
+                .map(|(_, acc)| acc)
+        }
+
+        fn insert(&mut self, client: u16, account: Account) -> Option<Account> {
+            match self.0.iter_mut().find(|(c, _)| *c == client) {
+                Some((_, existing)) => Some(std::mem::replace(existing, account)),
+                None => {
+                    self.0.push((client, account));
+                    None
+                }
+            }
+        }
+
+        fn remove(&mut self, client: &u16) -> Option<Account> {
+            let index = self.0.iter().position(|(c, _)| c == client)?;
+            Some(self.0.remove(index).1)
+        }
+
+        fn iter(&self) -> impl Iterator<Item = (&u16, &Account)> {
+            self.0.iter().map(|(c, acc)| (c, acc))
+        }
+    }
+
+    #[test]
+    fn should_process_transactions_with_an_alternate_account_store() {
+        let mut service: TransactionService<VecStore> = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::TRANSFER,
+            client: 1,
+            tx: 2,
+            amount: Some(0.50),
+            target_client: Some(2),
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.00, held: 0.00, total: 1.00, locked: false }), result);
+        assert_eq!(2, service.account_ledger.0.len());
+        assert_eq!(0.50, service.account_ledger.get(&2).unwrap().available());
+
+        let mut report = Vec::new();
+        service.write_report(&mut report).unwrap();
+        assert_eq!("client,available,held,total,locked\n\
+                    1,1.0000,0.0000,1.0000,false\n\
+                    2,0.5000,0.0000,0.5000,false\n", String::from_utf8(report).unwrap());
+    }
+
+    #[test]
+    fn should_resolve_a_partial_dispute() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: Some(0.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.00, held: 0.50, total: 1.50, locked: false }), result);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::RESOLVE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record3);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.50, held: 0.00, total: 1.50, locked: false }), result);
+    }
+
+    #[test]
+    fn should_charge_back_a_partial_dispute() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: Some(0.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::CHARGEBACK,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record3);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.00, held: 0.00, total: 1.00, locked: true }), result);
+        assert_eq!(0.50, service.transaction_ledger.get(&1).unwrap().charged_back);
+    }
+
+    #[test]
+    fn should_only_dispute_what_was_not_charged_back() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 1,
+                amount: Some(1.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DISPUTE,
+                client: 1,
+                tx: 1,
+                amount: Some(0.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::CHARGEBACK,
+                client: 1,
+                tx: 1,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::UNLOCK,
+                client: 1,
+                tx: 2,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+        ];
+        let _ = service.process_all(records);
+
+
+        assert_eq!(Err(TransactionError::DisputeExceedsTransaction), service.process(record(TransactionType::DISPUTE, 1, 1, Some(1.25))));
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.00, held: 1.00, total: 1.00, locked: false }), service.process(record(TransactionType::DISPUTE, 1, 1, None)));
+    }
+
+    #[test]
+    fn should_not_raise_a_partial_dispute_exceeding_the_transaction_amount() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: Some(2.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Err(TransactionError::DisputeExceedsTransaction), result);
+        assert!(service.dispute_ledger.is_empty());
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().available());
+    }
+
+    #[test]
+    fn should_not_raise_a_partial_dispute_of_zero_or_below() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(5.00)));
+
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::DISPUTE, 1, 1, Some(-2.00))));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::DISPUTE, 1, 1, Some(0.00))));
+        assert!(service.dispute_ledger.is_empty());
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(0.00, acc.held());
+        assert_eq!(5.00, acc.total());
+    }
+
+    #[test]
+    fn should_move_through_the_dispute_lifecycle() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+        assert_eq!(None, service.dispute_ledger.get(&1));
+
+        assert!(service.process(record(TransactionType::DISPUTE, 1, 1, None)).is_ok());
+        assert_eq!(Some(&DisputeState::Disputed(1.50)), service.dispute_ledger.get(&1));
+
+        assert!(service.process(record(TransactionType::RESOLVE, 1, 1, None)).is_ok());
+        assert_eq!(Some(&DisputeState::Resolved(1.50)), service.dispute_ledger.get(&1));
+
+        assert!(service.process(record(TransactionType::DISPUTE, 1, 1, None)).is_ok());
+        assert_eq!(Some(&DisputeState::Disputed(1.50)), service.dispute_ledger.get(&1));
+
+        assert!(service.process(record(TransactionType::CHARGEBACK, 1, 1, None)).is_ok());
+        assert_eq!(Some(&DisputeState::ChargedBack), service.dispute_ledger.get(&1));
+    }
+
+    #[test]
+    fn should_reject_invalid_dispute_transitions() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+
+        assert_eq!(Err(TransactionError::TransactionNotDisputed), service.process(record(TransactionType::CHARGEBACK, 1, 1, None)));
+
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        assert_eq!(Err(TransactionError::DisputeAlreadyExists), service.process(record(TransactionType::DISPUTE, 1, 1, None)));
+
+        let _ = service.process(record(TransactionType::RESOLVE, 1, 1, None));
+        assert_eq!(Err(TransactionError::TransactionNotDisputed), service.process(record(TransactionType::RESOLVE, 1, 1, None)));
+        assert_eq!(Err(TransactionError::TransactionNotDisputed), service.process(record(TransactionType::CHARGEBACK, 1, 1, None)));
+        assert_eq!(Some(&DisputeState::Resolved(1.50)), service.dispute_ledger.get(&1));
+    }
+
+    #[test]
+    fn should_round_report_rows_with_the_configured_rounding_mode() {
+        let mut service: TransactionService = Default::default();
+        service.account_ledger.insert(1, Account::from_parts(1, 1.00005, 0.0, 1.00005, false));
+
+        let report = |service: &TransactionService| {
+            let mut report = Vec::new();
+            service.write_report(&mut report).unwrap();
+            String::from_utf8(report).unwrap()
+        };
+
+        assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", report(&service));
+        service.set_rounding_mode(RoundingMode::HalfUp);
+        assert_eq!("client,available,held,total,locked\n1,1.0001,0.0000,1.0001,false\n", report(&service));
+        service.set_rounding_mode(RoundingMode::Truncate);
+        assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", report(&service));
+    }
+
+    #[test]
+    fn should_sum_the_balances_of_all_accounts() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 1,
+                amount: Some(1.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 2,
+                tx: 2,
+                amount: Some(2.00),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 3,
+                tx: 3,
+                amount: Some(0.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DISPUTE,
+                client: 2,
+                tx: 2,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DISPUTE,
+                client: 3,
+                tx: 3,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::CHARGEBACK,
+                client: 3,
+                tx: 3,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+        ];
+        let _ = service.process_all(records);
+
+        assert_eq!(SystemTotals { available: 1.50, held: 2.00, total: 3.50, locked_accounts: 1 }, service.totals());
+    }
+
+    #[test]
+    fn should_withdraw_under_the_withdrawal_limit() {
+        let mut service: TransactionService = Default::default();
+        service.set_max_withdrawal(Some(1.00));
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 2,
+            amount: Some(1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.50, held: 0.00, total: 0.50, locked: false }), result);
+    }
+
+    #[test]
+    fn should_not_withdraw_over_the_withdrawal_limit() {
+        let mut service: TransactionService = Default::default();
+        service.set_max_withdrawal(Some(1.00));
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        // The limit is checked before the funds, so this does not fail with InsufficientFunds.
+        let record2 = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 2,
+            amount: Some(2.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let result = service.process(record2);
+
+        assert_eq!(Err(TransactionError::WithdrawalLimitExceeded), result);
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().available());
+    }
+
+    #[test]
+    fn should_record_applied_transactions_in_the_audit_log() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 1,
+                amount: Some(1.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::WITHDRAWAL,
+                client: 1,
+                tx: 2,
+                amount: Some(5.00),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+            TransactionRecord {
+                _type: TransactionType::DISPUTE,
+                client: 1,
+                tx: 1,
+                amount: None,
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            },
+        ];
+        let _ = service.process_all(records);
+
+        assert_eq!(&[
+            AuditEvent::Transaction {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 1,
+                amount: Some(1.50),
+                target: None,
+                currency: None,
+                reference: None,
+                timestamp: None,
+                account: AccountSnapshot { client: 1, available: 1.50, held: 0.00, total: 1.50, locked: false },
+            },
+            AuditEvent::Transaction {
+                _type: TransactionType::DISPUTE,
+                client: 1,
+                tx: 1,
+                amount: None,
+                target: None,
+                currency: None,
+                reference: None,
+                timestamp: None,
+                account: AccountSnapshot { client: 1, available: 0.00, held: 1.50, total: 1.50, locked: false },
+            },
+        ], service.audit_log());
+        assert_eq!(
+            r#"{"event":"transaction","type":"deposit","client":1,"tx":1,"amount":1.5,"timestamp":null,"account":{"client":1,"available":1.5,"held":0.0,"total":1.5,"locked":false}}"#,
+            serde_json::to_string(&service.audit_log()[0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_not_settle_a_dispute_with_a_record_carrying_an_amount() {
+        let mut service: TransactionService = Default::default();
+        let record1 = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record1);
+
+        let record2 = TransactionRecord {
+            _type: TransactionType::DISPUTE,
+            client: 1,
+            tx: 1,
+            amount: None,
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record2);
+
+        let record3 = TransactionRecord {
+            _type: TransactionType::RESOLVE,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        assert_eq!(Err(TransactionError::UnexpectedAmount), service.process(record3));
+
+        let record4 = TransactionRecord {
+            _type: TransactionType::CHARGEBACK,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        assert_eq!(Err(TransactionError::UnexpectedAmount), service.process(record4));
+
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(0.00, acc.available());
+        assert_eq!(1.50, acc.held());
+        assert!(!acc.locked());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn should_process_a_deposit_asynchronously() {
+        let mut service: TransactionService = Default::default();
+        let record = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let result = service.process_async(record).await;
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.5, held: 0.0, total: 1.5, locked: false }), result);
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().available());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn should_process_records_from_a_stream() {
+        let mut service: TransactionService = Default::default();
+        let stream = futures::stream::iter(vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(2.00)),
+            record(TransactionType::WITHDRAWAL, 1, 2, Some(0.50)),
+            record(TransactionType::WITHDRAWAL, 1, 3, Some(5.00)),
+        ]);
+
+        let results = service.process_stream(stream).await;
+
+        assert_eq!(vec![1, 2, 3], results.iter().map(|(tx, _)| *tx).collect::<Vec<_>>());
+        assert_eq!(Err(TransactionError::InsufficientFunds), results[2].1);
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().available());
+    }
+
+    #[test]
+    fn should_reopen_a_resolved_dispute() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        let _ = service.process(record(TransactionType::RESOLVE, 1, 1, None));
+
+        let result = service.process(record(TransactionType::REOPEN, 1, 1, None));
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.0, held: 1.5, total: 1.5, locked: false }), result);
+        assert_eq!(Some(&DisputeState::Disputed(1.50)), service.dispute_ledger.get(&1));
+        assert_eq!(Err(TransactionError::DisputeAlreadyExists), service.process(record(TransactionType::REOPEN, 1, 1, None)));
+    }
+
+    #[test]
+    fn should_not_reopen_a_charged_back_or_undisputed_transaction() {
+        let mut service: TransactionService = Default::default();
+        for tx in 1..=2 {
+            let _ = service.process(TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx,
+                amount: Some(1.50),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            });
+        }
+
+        assert_eq!(Err(TransactionError::TransactionNotDisputed), service.process(record(TransactionType::REOPEN, 1, 2, None)));
+
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        let _ = service.process(record(TransactionType::CHARGEBACK, 1, 1, None));
+        let _ = service.process(record(TransactionType::UNLOCK, 1, 1, None));
+
+        assert_eq!(Err(TransactionError::TransactionNotResolved), service.process(record(TransactionType::REOPEN, 1, 1, None)));
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(1.50, acc.available());
+        assert_eq!(0.00, acc.held());
+    }
+
+    #[test]
+    fn should_accrue_interest_on_the_available_balance() {
+        let mut service: TransactionService = Default::default();
+        let record = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(100.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record);
+
+        let result = service.accrue_interest(1, 0.012345);
+
+        let account = AccountSnapshot { client: 1, available: 101.2345, held: 0.00, total: 101.2345, locked: false };
+        assert_eq!(Ok(account.clone()), result);
+        assert_eq!(Some(&AuditEvent::InterestAccrued { client: 1, rate: 0.012345, amount: 1.2345, account }),
+                   service.audit_log().last());
+        assert_eq!(Err(TransactionError::AccountNotFound(2)), service.accrue_interest(2, 0.01));
+    }
+
+    #[test]
+    fn should_not_accrue_interest_on_a_locked_account() {
+        let mut service: TransactionService = Default::default();
+        service.account_ledger.insert(1, Account::from_parts(1, 10.00, 0.00, 10.00, true));
+
+        assert_eq!(Err(TransactionError::LockedAccount), service.accrue_interest(1, 0.05));
+        assert_eq!(10.00, service.account_ledger.get(&1).unwrap().available());
+        assert!(service.audit_log().is_empty());
+    }
+
+    #[test]
+    fn should_not_charge_interest_on_an_overdrawn_account() {
+        let mut service: TransactionService = Default::default();
+        service.account_ledger.insert(1, Account::from_parts(1, -4.00, 0.00, -4.00, false));
+
+        let snapshot = service.accrue_interest(1, 0.5).unwrap();
+
+        assert_eq!(-4.00, snapshot.available);
+        assert_eq!(-4.00, snapshot.total);
+        assert_eq!(Some(&0.00), service.accrued_interest.get(&1));
+    }
+
+    #[test]
+    fn should_not_accrue_interest_at_an_invalid_rate() {
+        let mut service: TransactionService = Default::default();
+        service.account_ledger.insert(1, Account::from_parts(1, 5.00, 0.00, 5.00, false));
+
+        assert_eq!(Err(TransactionError::InvalidRate), service.accrue_interest(1, -3.0));
+        assert_eq!(Err(TransactionError::InvalidRate), service.accrue_interest(1, f32::NEG_INFINITY));
+        assert_eq!(Err(TransactionError::AmountOutOfRange), service.accrue_interest(1, f32::NAN));
+        assert_eq!(5.00, service.account_ledger.get(&1).unwrap().available());
+        assert!(service.audit_log().is_empty());
+    }
+
+    #[test]
+    fn should_write_the_report_header_without_any_accounts() {
+        let service: TransactionService = Default::default();
+        let mut report = Vec::new();
+        service.write_report(&mut report).unwrap();
+
+        assert_eq!("client,available,held,total,locked\n", String::from_utf8(report).unwrap());
+    }
+
+    #[test]
+    fn should_keep_accounts_balanced_through_a_dispute_cycle() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(1.1234)),
+            record(TransactionType::DISPUTE, 1, 1, None),
+            record(TransactionType::RESOLVE, 1, 1, None),
+        ];
+
+        for record in records {
+            assert!(service.process(record).is_ok());
+            assert_eq!(Ok(()), service.check_invariants());
+        }
+
+        service.account_ledger.insert(2, Account::from_parts(2, 1.00, 0.00, 2.00, false));
+        assert_eq!(Err(TransactionError::UnbalancedAccount(2)), service.check_invariants());
+    }
+
+    #[test]
+    fn should_list_the_transactions_of_a_client_in_order() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            record(TransactionType::DEPOSIT, 1, 3, Some(1.00)),
+            record(TransactionType::DEPOSIT, 2, 2, Some(1.00)),
+            record(TransactionType::WITHDRAWAL, 1, 1, Some(1.00)),
+            record(TransactionType::WITHDRAWAL, 1, 4, Some(1.00)),
+            record(TransactionType::DEPOSIT, 1, 5, Some(1.00)),
+        ];
+        let _ = service.process_all(records);
+
+        assert_eq!(vec![3, 1, 5], service.client_transactions(1));
+        assert_eq!(vec![2], service.client_transactions(2));
+        assert!(service.client_transactions(3).is_empty());
+    }
+
+    #[test]
+    fn should_not_dispute_a_transaction_of_another_client() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 2, 2, Some(1.50)));
+        let foreign = |_type| record(_type, 2, 1, None);
+
+        assert_eq!(Err(TransactionError::TransactionClientMismatch), service.process(foreign(TransactionType::DISPUTE)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        assert_eq!(Err(TransactionError::TransactionClientMismatch), service.process(foreign(TransactionType::RESOLVE)));
+        assert_eq!(Err(TransactionError::TransactionClientMismatch), service.process(foreign(TransactionType::CHARGEBACK)));
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().held());
+        assert_eq!(1.50, service.account_ledger.get(&2).unwrap().available());
+    }
+
+    #[test]
+    fn should_record_an_alert_when_held_crosses_the_threshold() {
+        let mut service = TransactionService::builder()
+            .held_alert_threshold(2.00)
+            .build();
+        for tx in 1..=3 {
+            let _ = service.process(record(TransactionType::DEPOSIT, 1, tx, Some(1.50)));
+        }
+
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        assert!(!service.audit_log().iter().any(|event| matches!(event, AuditEvent::HeldThresholdExceeded { .. })));
+
+        let result = service.process(record(TransactionType::DISPUTE, 1, 2, None));
+        assert!(result.is_ok());
+        assert_eq!(Some(&AuditEvent::HeldThresholdExceeded { client: 1, held: 3.00 }), service.audit_log().last());
+
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 3, None));
+        let alerts = service.audit_log().iter()
+            .filter(|event| matches!(event, AuditEvent::HeldThresholdExceeded { .. }))
+            .count();
+        assert_eq!(1, alerts);
+        assert_eq!(4.50, service.account_ledger.get(&1).unwrap().held());
+    }
+
+    #[test]
+    fn should_open_an_empty_account() {
+        let mut service: TransactionService = Default::default();
+
+        let result = service.open_account(1);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.0, held: 0.0, total: 0.0, locked: false }), result);
+        assert!(service.account_ledger.contains_key(&1));
+    }
+
+    #[test]
+    fn should_not_open_an_account_twice() {
+        let mut service: TransactionService = Default::default();
+        let record = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(1.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(record);
+
+        assert_eq!(Err(TransactionError::AccountAlreadyExists(1)), service.open_account(1));
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_report_the_same_balance_at_different_scales() {
+        let report = |scale| {
+            let mut service = TransactionService::builder()
+                .scale(scale)
+                .build();
+            let record = TransactionRecord {
+                _type: TransactionType::DEPOSIT,
+                client: 1,
+                tx: 1,
+                amount: Some(12.00),
+                target_client: None,
+                timestamp: None,
+                currency: None,
+                reference: None,
+            };
+            let _ = service.process(record);
+            let mut report = Vec::new();
+            service.write_report(&mut report).unwrap();
+            String::from_utf8(report).unwrap()
+        };
+
+        assert_eq!("client,available,held,total,locked\n1,12,0,12,false\n", report(0));
+        assert_eq!("client,available,held,total,locked\n1,12.00,0.00,12.00,false\n", report(2));
+        assert_eq!("client,available,held,total,locked\n1,12.0000,0.0000,12.0000,false\n", report(4));
+    }
+
+    #[test]
+    fn should_reject_amounts_with_more_decimals_than_the_scale() {
+        let mut service = TransactionService::builder()
+            .scale(2)
+            .build();
+
+        assert_eq!(Err(TransactionError::AmountExceedsScale), service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.005))));
+        assert!(service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.1))).is_ok());
+        assert!(service.process(record(TransactionType::DEPOSIT, 1, 3, Some(1.05))).is_ok());
+        assert_eq!(2.15, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_report_that_a_chargeback_locked_the_account() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+
+        let result = service.process(record(TransactionType::CHARGEBACK, 1, 1, None));
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 0.0, held: 0.0, total: 0.0, locked: true }), result);
+        assert_eq!(Some(&AuditEvent::AccountLocked { client: 1, tx: 1 }), service.audit_log().last());
+    }
+
+    #[test]
+    fn should_ignore_a_replayed_deposit_when_idempotent() {
+        let mut service = TransactionService::builder()
+            .idempotent(true)
+            .build();
+
+        assert!(service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50))).is_ok());
+        let result = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 1.5, held: 0.0, total: 1.5, locked: false }), result);
+        assert_eq!(1, service.audit_log().len());
+    }
+
+    #[test]
+    fn should_only_ignore_replays_of_the_same_client() {
+        let mut service = TransactionService::builder()
+            .idempotent(true)
+            .build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::CLOSE, 1, 3, None));
+
+        assert_eq!(Err(TransactionError::InvalidAccount { client: 1, tx: 1 }), service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00))));
+        assert!(service.process(record(TransactionType::DEPOSIT, 2, 1, Some(1.00))).is_ok());
+        assert_eq!(1.00, service.account_ledger.get(&2).unwrap().total());
+        assert!(!service.account_ledger.contains_key(&1));
+        assert_eq!(Some(&TransactionTypeStats { succeeded: 2, failed: 1 }), service.stats().by_type.get(&TransactionType::DEPOSIT));
+    }
+
+    #[test]
+    fn should_keep_ignoring_replays_after_a_round_trip() {
+        let mut service = TransactionService::builder()
+            .idempotent(true)
+            .scale(2)
+            .allow_on_locked(TransactionType::DEPOSIT)
+            .allow_on_locked(TransactionType::REFUND)
+            .build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(5.00)));
+
+        let mut from_json: TransactionService = TransactionService::from_json(&service.to_json().unwrap()).unwrap();
+        let mut from_bincode: TransactionService = TransactionService::from_bincode(&service.to_bincode()).unwrap();
+        for restored in [&mut from_json, &mut from_bincode] {
+            let _ = restored.process(record(TransactionType::DEPOSIT, 1, 1, Some(5.00)));
+
+            assert_eq!(5.00, restored.account_ledger.get(&1).unwrap().total());
+            assert_eq!(2, restored.scale());
+            assert_eq!(service.to_json().unwrap(), restored.to_json().unwrap());
+        }
+    }
+
+    #[test]
+    fn should_apply_a_replayed_deposit_again_by_default() {
+        let mut service: TransactionService = Default::default();
+
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+
+        assert_eq!(3.00, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_reject_financial_operations_on_a_frozen_account_until_thawed() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+
+        service.freeze(1);
+        assert_eq!(Err(TransactionError::FrozenAccount), service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.50))));
+        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().total());
+
+        service.thaw(1);
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 3.0, held: 0.0, total: 3.0, locked: false }), service.process(record(TransactionType::DEPOSIT, 1, 3, Some(1.50))));
+    }
+
+    #[test]
+    fn should_not_transfer_into_or_accrue_interest_on_a_frozen_account() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 2, 2, Some(1.00)));
+        service.freeze(2);
+
+        assert_eq!(Err(TransactionError::FrozenAccount), service.process(record_to(TransactionType::TRANSFER, 1, 3, Some(1.00), Some(2))));
+        assert_eq!(Err(TransactionError::FrozenAccount), service.accrue_interest(2, 0.10));
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().total());
+        assert_eq!(1.00, service.account_ledger.get(&2).unwrap().total());
+
+        service.thaw(2);
+        assert!(service.process(record_to(TransactionType::TRANSFER, 1, 3, Some(1.00), Some(2))).is_ok());
+        assert_eq!(2.00, service.account_ledger.get(&2).unwrap().total());
+    }
+
+    #[test]
+    fn should_reconcile_the_ledgers_with_the_accounts() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(10.00)),
+            record(TransactionType::WITHDRAWAL, 1, 2, Some(2.50)),
+            record_to(TransactionType::TRANSFER, 1, 3, Some(1.50), Some(2)),
+            record(TransactionType::DEPOSIT, 2, 4, Some(4.00)),
+            record(TransactionType::DISPUTE, 2, 4, None),
+            record(TransactionType::ADJUSTMENT, 1, 5, Some(-0.25)),
+            record(TransactionType::DEPOSIT, 3, 6, Some(1.00)),
+            record(TransactionType::DISPUTE, 3, 6, None),
+            record(TransactionType::CHARGEBACK, 3, 6, None),
+        ];
+        for record in records {
+            assert!(service.process(record).is_ok());
+        }
+        let _ = service.accrue_interest(1, 0.10);
+
+        assert_eq!(Ok(()), service.reconcile());
+    }
+
+    #[test]
+    fn should_report_accounts_which_do_not_reconcile() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 2, 2, Some(1.50)));
+
+        service.account_ledger.insert(2, Account::from_parts(2, 2.00, 0.00, 2.00, false));
+
+        assert_eq!(Err(ReconciliationMismatch {
+            discrepancies: vec![Discrepancy { client: 2, expected: 1.50, actual: 2.00 }],
+        }), service.reconcile());
+    }
+
+    #[test]
+    fn should_tell_whether_a_transaction_is_disputed() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.50)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+
+        assert!(service.is_disputed(1));
+        assert!(!service.is_disputed(2));
+        assert!(!service.is_disputed(3));
+
+        let _ = service.process(record(TransactionType::RESOLVE, 1, 1, None));
+        assert!(!service.is_disputed(1));
+    }
+
+    #[test]
+    fn should_resolve_all_open_disputes_of_a_client() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(1.50)),
+            record(TransactionType::DEPOSIT, 1, 2, Some(2.50)),
+            record(TransactionType::DEPOSIT, 2, 3, Some(1.00)),
+            record(TransactionType::DISPUTE, 1, 2, None),
+            record(TransactionType::DISPUTE, 1, 1, None),
+            record(TransactionType::DISPUTE, 2, 3, None),
+        ];
+        let _ = service.process_all(records);
+
+        let results = service.resolve_all(1);
+
+        assert_eq!(vec![1, 2], results.iter().map(|(tx, _)| *tx).collect::<Vec<u32>>());
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        let acc = service.account_ledger.get(&1).unwrap();
+        assert_eq!(0.00, acc.held());
+        assert_eq!(4.00, acc.available());
+        assert!(service.is_disputed(3));
+    }
+
+    #[test]
+    fn should_decline_withdrawals_for_unknown_clients_based_on_the_policy() {
+
+        let mut service: TransactionService = Default::default();
+        assert_eq!(Err(TransactionError::InvalidAccount { client: 1, tx: 1 }), service.process(record(TransactionType::WITHDRAWAL, 1, 1, Some(1.00))));
+
+        service.set_missing_account_policy(MissingAccountPolicy::TreatAsEmpty);
+        assert_eq!(Err(TransactionError::InsufficientFunds), service.process(record(TransactionType::WITHDRAWAL, 1, 1, Some(1.00))));
+        assert!(!service.account_ledger.contains_key(&1));
+    }
+
+    #[test]
+    fn should_open_an_account_for_an_unknown_client_who_may_overdraw() {
+        let mut service = TransactionService::builder()
+            .missing_account_policy(MissingAccountPolicy::TreatAsEmpty)
+            .overdraft_limit(1, 5.00)
+            .build();
+        let withdrawal = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 1,
+            amount: Some(1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        let result = service.process(withdrawal);
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: -1.00, held: 0.00, total: -1.00, locked: false }), result);
+    }
+
+    #[test]
+    fn should_round_trip_the_state_through_json() {
+        let mut service: TransactionService = Default::default();
+        let records = vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(1.50)),
+            record(TransactionType::DEPOSIT, 2, 2, Some(2.50)),
+            record(TransactionType::DEPOSIT, 10, 3, Some(0.75)),
+            record(TransactionType::DISPUTE, 2, 2, None),
+        ];
+        let _ = service.process_all(records);
+        service.freeze(10);
+        service.freeze(1);
+
+        let json = service.to_json().unwrap();
+        let restored = TransactionService::from_json(&json).unwrap();
+
+        let report = |service: &TransactionService| {
+            let mut report = Vec::new();
+            service.write_report(&mut report).unwrap();
+            String::from_utf8(report).unwrap()
+        };
+        assert_eq!(report(&service), report(&restored));
+        assert_eq!(json, restored.to_json().unwrap());
+        assert!(restored.is_disputed(2));
+    }
+
+    #[test]
+    fn should_cap_the_number_of_open_disputes_per_client() {
+        let mut service = TransactionService::builder()
+            .max_open_disputes(2)
+            .build();
+        for tx in 1..=3 {
+            let _ = service.process(record(TransactionType::DEPOSIT, 1, tx, Some(1.00)));
+        }
+        let _ = service.process(record(TransactionType::DEPOSIT, 2, 4, Some(1.00)));
+
+        assert!(service.process(record(TransactionType::DISPUTE, 1, 1, None)).is_ok());
+        assert!(service.process(record(TransactionType::DISPUTE, 1, 2, None)).is_ok());
+        assert_eq!(Err(TransactionError::TooManyOpenDisputes), service.process(record(TransactionType::DISPUTE, 1, 3, None)));
+        assert!(service.process(record(TransactionType::DISPUTE, 2, 4, None)).is_ok());
+
+        let _ = service.process(record(TransactionType::RESOLVE, 1, 1, None));
+        assert!(service.process(record(TransactionType::DISPUTE, 1, 3, None)).is_ok());
+        assert_eq!(Err(TransactionError::TooManyOpenDisputes), service.process(record(TransactionType::REOPEN, 1, 1, None)));
+    }
+
+    #[test]
+    fn should_charge_a_flat_fee_on_withdrawals() {
+        let mut service = TransactionService::builder()
+            .withdrawal_fee(FeePolicy::Flat(0.50), 99)
+            .build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(5.00)));
+
+        let result = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(2.00)));
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 2.50, held: 0.00, total: 2.50, locked: false }), result);
+        assert_eq!(0.50, service.account_ledger.get(&99).unwrap().total());
+        assert_eq!(Err(TransactionError::InsufficientFunds), service.process(record(TransactionType::WITHDRAWAL, 1, 3, Some(2.50))));
+        assert_eq!(Ok(()), service.reconcile());
+    }
+
+    #[test]
+    fn should_not_charge_the_fee_client_a_fee() {
+        let mut service = TransactionService::builder()
+            .withdrawal_fee(FeePolicy::Flat(1.00), 1)
+            .build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(5.00)));
+
+        let result = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(2.00)));
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 3.00, held: 0.00, total: 3.00, locked: false }), result);
+        assert!(service.transfer_ledger.is_empty());
+        assert_eq!(Ok(()), service.reconcile());
+        assert_eq!(Ok(()), service.undo_last());
+        assert_eq!(5.00, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_charge_a_percentage_fee_on_withdrawals() {
+        let mut service: TransactionService = Default::default();
+        service.set_withdrawal_fee(FeePolicy::Percentage(0.015), 99);
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(10.00)));
+
+        let result = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(4.00)));
+
+        assert_eq!(Ok(AccountSnapshot { client: 1, available: 5.94, held: 0.00, total: 5.94, locked: false }), result);
+        assert_eq!(0.06, service.account_ledger.get(&99).unwrap().available());
+    }
+
+    #[test]
+    fn should_invoke_hooks_for_every_processed_record() {
+        use std::sync::{Arc, Mutex};
+
+        let mut service: TransactionService = Default::default();
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&outcomes);
+        service.on_processed(move |record, result| {
+            recorded.lock().unwrap().push((record.tx, result.is_ok()));
+        });
+
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 3, Some(1.00)));
+
+        assert_eq!(vec![(1, true), (2, true), (3, false)], *outcomes.lock().unwrap());
+    }
+
+    #[test]
+    fn should_not_open_accounts_beyond_the_account_limit() {
+        let mut service = TransactionService::builder()
+            .max_accounts(1)
+            .build();
+
+        assert!(service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00))).is_ok());
+        assert_eq!(Err(TransactionError::AccountLimitReached(2)), service.process(record(TransactionType::DEPOSIT, 2, 2, Some(1.00))));
+        assert!(service.process(record(TransactionType::DEPOSIT, 1, 3, Some(1.00))).is_ok());
+        assert_eq!(Err(TransactionError::AccountLimitReached(2)), service.open_account(2));
+        assert_eq!(1, service.accounts().count());
+    }
+
+    #[test]
+    fn should_undo_the_last_deposit() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(2.00)));
+
+        assert_eq!(Ok(()), service.undo_last());
+
+        let account = service.account_ledger.get(&1).unwrap();
+        assert_eq!(1.00, account.available());
+        assert_eq!(1.00, account.total());
+        assert!(!service.transaction_ledger.contains_key(&2));
+        assert_eq!(vec![1], service.client_transactions(1));
+        assert_eq!(Ok(()), service.undo_last());
+        assert_eq!(Err(TransactionError::MissingTransaction), service.undo_last());
+    }
+
+    #[test]
+    fn should_replay_an_undone_deposit_as_undone() {
+        let mut service = TransactionService::builder().track_balance_history(true).build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(2.00)));
+
+        assert_eq!(Ok(()), service.undo_last());
+
+        let account = AccountSnapshot { client: 1, available: 1.00, held: 0.00, total: 1.00, locked: false };
+        assert_eq!(Some(&AuditEvent::Undone { client: 1, tx: 2, account }), service.audit_log().last());
+        assert_eq!(1, service.stats().succeeded);
+        assert_eq!(Some(&TransactionTypeStats { succeeded: 1, failed: 0 }), service.stats().by_type.get(&TransactionType::DEPOSIT));
+        assert_eq!(Some(&BalancePoint { tx: 2, available: 1.00, held: 0.00, total: 1.00 }), service.balance_history(1).last());
+
+        let replayed = TransactionService::replay(service.audit_log()).unwrap();
+        assert_eq!(1.00, replayed.account_ledger.get(&1).unwrap().total());
+        assert!(!replayed.transaction_ledger.contains_key(&2));
+        assert_eq!(service.audit_log(), replayed.audit_log());
+    }
+
+    #[test]
+    fn should_not_undo_a_disputed_transaction() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+
+        assert_eq!(Err(TransactionError::DisputeAlreadyExists), service.undo_last());
+        assert!(service.transaction_ledger.contains_key(&1));
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().held());
+    }
+
+    #[test]
+    fn should_not_log_anything_when_logging_is_off() {
+        use std::sync::Mutex;
+        use std::thread::{self, ThreadId};
+
+        static MESSAGES: Mutex<Vec<(ThreadId, String)>> = Mutex::new(Vec::new());
+        struct CapturingLogger;
+        impl log::Log for CapturingLogger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+            fn log(&self, record: &log::Record) {
+                MESSAGES.lock().unwrap().push((thread::current().id(), record.args().to_string()));
+            }
+            fn flush(&self) {}
+        }
+        static LOGGER: CapturingLogger = CapturingLogger;
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(LevelFilter::Trace);
+        // Other tests run on their own threads, so only count the messages of this one.
+        let logged = || MESSAGES.lock().unwrap().iter()
+            .filter(|(thread, _)| *thread == thread::current().id())
+            .count();
+
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 1, Some(1.00)));
+        assert!(logged() > 0);
+
+        let before = logged();
+        let mut service = TransactionService::builder()
+            .log_level(LevelFilter::Off)
+            .build();
+        service.freeze(1);
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(1.00)));
+        assert_eq!(before, logged());
+    }
+
+    #[test]
+    fn should_reverse_funds_without_locking_on_a_soft_chargeback() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(2.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+
+        let snapshot = service.process(record(TransactionType::SOFT_CHARGEBACK, 1, 1, None)).unwrap();
+
+        assert_eq!(1.00, snapshot.available);
+        assert_eq!(0.00, snapshot.held);
+        assert_eq!(1.00, snapshot.total);
+        assert!(!snapshot.locked);
+        assert!(!service.is_disputed(1));
+        assert!(service.process(record(TransactionType::WITHDRAWAL, 1, 3, Some(0.50))).is_ok());
+        assert!(service.audit_log().iter().all(|event| !matches!(event, AuditEvent::AccountLocked { .. })));
+    }
+
+    #[test]
+    fn should_reject_amounts_that_overflow_the_balance() {
+        let mut service: TransactionService = Default::default();
+
+        assert!(service.process(record(TransactionType::DEPOSIT, 1, 1, Some(f32::MAX))).is_ok());
+        assert_eq!(Err(TransactionError::AmountOutOfRange), service.process(record(TransactionType::DEPOSIT, 1, 2, Some(f32::MAX))));
+        assert_eq!(Err(TransactionError::AmountOutOfRange), service.process(record(TransactionType::DEPOSIT, 1, 3, Some(f32::INFINITY))));
+        assert_eq!(Err(TransactionError::AmountOutOfRange), service.process(record(TransactionType::DEPOSIT, 1, 4, Some(f32::NAN))));
+        assert_eq!(f32::MAX, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_rebuild_the_same_balances_by_replaying_the_audit_log() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process_all(vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(5.00)),
+            record(TransactionType::DEPOSIT, 2, 2, Some(3.00)),
+            record(TransactionType::WITHDRAWAL, 1, 3, Some(1.00)),
+            record_to(TransactionType::TRANSFER, 1, 4, Some(1.50), Some(3)),
+            record(TransactionType::DISPUTE, 2, 2, None),
+            record(TransactionType::CHARGEBACK, 2, 2, None),
+            record(TransactionType::WITHDRAWAL, 3, 5, Some(9.00)),
+        ]);
+        service.accrue_interest(1, 0.10).unwrap();
+        service.place_hold(1, 6, 1.00).unwrap();
+        service.place_hold(1, 7, 0.50).unwrap();
+        service.place_hold(3, 8, 1.00).unwrap();
+        service.release_hold(1, 6).unwrap();
+        service.capture_hold(3, 8).unwrap();
+
+        let replayed = TransactionService::replay(service.audit_log()).unwrap();
+
+        let report = |service: &TransactionService| {
+            let mut report = Vec::new();
+            service.write_report(&mut report).unwrap();
+            String::from_utf8(report).unwrap()
+        };
+        assert_eq!(report(&service), report(&replayed));
+        assert_eq!(service.audit_log(), replayed.audit_log());
+    }
+
+    #[test]
+    fn should_replay_an_audit_log_read_back_from_json() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process_all(vec![
+            TransactionRecord { reference: Some(String::from("INV-1")), ..record(TransactionType::DEPOSIT, 1, 1, Some(5.00)) },
+            record(TransactionType::DEPOSIT, 2, 2, Some(3.00)),
+            record_to(TransactionType::TRANSFER, 1, 3, Some(1.50), Some(3)),
+            record(TransactionType::DISPUTE, 2, 2, None),
+            record(TransactionType::CHARGEBACK, 2, 2, None),
+            record(TransactionType::DEPOSIT, 1, 4, Some(2.00)),
+        ]);
+        service.undo_last().unwrap();
+        service.accrue_interest(1, 0.10).unwrap();
+        service.place_hold(1, 5, 1.00).unwrap();
+        service.capture_hold(1, 5).unwrap();
+
+        let json = serde_json::to_string(service.audit_log()).unwrap();
+        let events: Vec<AuditEvent> = serde_json::from_str(&json).unwrap();
+        let replayed = TransactionService::replay(&events).unwrap();
+
+        assert_eq!(service.audit_log(), &events[..]);
+        assert_eq!(service.report_filtered(AccountFilter::All), replayed.report_filtered(AccountFilter::All));
+        assert_eq!(service.audit_log(), replayed.audit_log());
+    }
+
+    #[test]
+    fn should_place_and_release_a_hold() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(5.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+
+        let held = service.place_hold(1, 10, 2.00).unwrap();
+        assert_eq!(3.00, held.available);
+        assert_eq!(2.00, held.held);
+        assert_eq!(5.00, held.total);
+        assert_eq!(Err(TransactionError::HoldAlreadyExists), service.place_hold(1, 10, 1.00));
+        assert_eq!(Err(TransactionError::InsufficientFunds), service.place_hold(1, 11, 4.00));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.place_hold(1, 11, 0.00));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.place_hold(1, 11, -1.00));
+        assert_eq!(Err(TransactionError::AmountExceedsScale), service.place_hold(1, 11, 0.00001));
+        assert!(!service.is_disputed(10));
+
+        let released = service.release_hold(1, 10).unwrap();
+        assert_eq!(5.00, released.available);
+        assert_eq!(0.00, released.held);
+        assert_eq!(5.00, released.total);
+        assert_eq!(Err(TransactionError::MissingHold), service.release_hold(1, 10));
+        assert_eq!(Ok(()), service.reconcile());
+    }
+
+    #[test]
+    fn should_debit_the_total_when_capturing_a_hold() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(5.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
+        let _ = service.place_hold(1, 10, 2.00);
+
+        assert_eq!(Err(TransactionError::TransactionClientMismatch), service.capture_hold(2, 10));
+        let captured = service.capture_hold(1, 10).unwrap();
+
+        assert_eq!(3.00, captured.available);
+        assert_eq!(0.00, captured.held);
+        assert_eq!(3.00, captured.total);
+        assert!(!captured.locked);
+        assert_eq!(Err(TransactionError::MissingHold), service.capture_hold(1, 10));
+        assert_eq!(Err(TransactionError::MissingHold), service.release_hold(1, 10));
+        assert_eq!(Ok(()), service.reconcile());
+    }
+
+    #[test]
+    fn should_withdraw_an_exact_balance_despite_rounding() {
+        let records = || vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(0.7)),
+            record(TransactionType::DEPOSIT, 1, 2, Some(0.9)),
+        ];
+
+        // 0.7 + 0.9 is slightly less than 1.6 in f32.
+        let mut service: TransactionService = Default::default();
+        let _ = service.process_all(records());
+        assert!(service.process(record(TransactionType::WITHDRAWAL, 1, 3, Some(1.6))).is_ok());
+
+        let mut strict = TransactionService::builder()
+            .epsilon(0.00)
+            .build();
+        let _ = strict.process_all(records());
+        assert_eq!(Err(TransactionError::InsufficientFunds), strict.process(record(TransactionType::WITHDRAWAL, 1, 3, Some(1.6))));
+    }
+
+    #[test]
+    fn should_reject_operations_across_currencies() {
+        let mut service: TransactionService = Default::default();
+        let in_currency = |currency: &str, record| TransactionRecord { currency: Some(currency.to_string()), ..record };
+        let _ = service.process(in_currency("USD", record(TransactionType::DEPOSIT, 1, 1, Some(1.00))));
+        let _ = service.process(in_currency("EUR", record(TransactionType::DEPOSIT, 2, 2, Some(1.00))));
+
+        assert!(service.process(in_currency("USD", record(TransactionType::DEPOSIT, 1, 3, Some(1.00)))).is_ok());
+        assert_eq!(Err(TransactionError::CurrencyMismatch), service.process(in_currency("EUR", record(TransactionType::DEPOSIT, 1, 4, Some(1.00)))));
+        assert_eq!(Err(TransactionError::CurrencyMismatch), service.process(in_currency("EUR", record(TransactionType::WITHDRAWAL, 1, 5, Some(1.00)))));
+        assert_eq!(Err(TransactionError::CurrencyMismatch), service.process(in_currency("USD", record_to(TransactionType::TRANSFER, 1, 6, Some(1.00), Some(2)))));
+        assert!(service.process(in_currency("USD", record_to(TransactionType::TRANSFER, 1, 7, Some(1.00), Some(3)))).is_ok());
+        assert_eq!(Some("USD"), service.account_ledger.get(&3).unwrap().currency());
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_block_records_rejected_by_a_risk_check() {
+        let mut service: TransactionService = Default::default();
+        service.add_risk_check(|record, _| {
+            (record._type == TransactionType::DEPOSIT && record.amount.is_some_and(|amount| amount > 100.00))
+                .then_some(TransactionError::InvalidOperation)
+        });
+
+        assert!(service.process(record(TransactionType::DEPOSIT, 1, 1, Some(100.00))).is_ok());
+        assert_eq!(Err(TransactionError::InvalidOperation), service.process(record(TransactionType::DEPOSIT, 1, 2, Some(500.00))));
+
+        assert_eq!(100.00, service.account_ledger.get(&1).unwrap().total());
+        assert!(!service.transaction_ledger.contains_key(&2));
+        assert_eq!(1, service.audit_log().len());
+    }
+
+    #[test]
+    fn should_filter_the_report_by_lock_state() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process_all(vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(1.00)),
+            record(TransactionType::DEPOSIT, 2, 2, Some(2.00)),
+            record(TransactionType::DISPUTE, 2, 2, None),
+            record(TransactionType::CHARGEBACK, 2, 2, None),
+        ]);
+
+        assert_eq!("client,available,held,total,locked
+1,1.0000,0.0000,1.0000,false
+2,0.0000,0.0000,0.0000,true
+", service.report_filtered(AccountFilter::All));
+        assert_eq!("client,available,held,total,locked
+2,0.0000,0.0000,0.0000,true
+", service.report_filtered(AccountFilter::LockedOnly));
+        assert_eq!("client,available,held,total,locked
+1,1.0000,0.0000,1.0000,false
+", service.report_filtered(AccountFilter::ActiveOnly));
+    }
+
+    #[test]
+    fn should_keep_the_balance_history_of_each_client() {
+        let mut service = TransactionService::builder()
+            .track_balance_history(true)
+            .build();
+        let _ = service.process_all(vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(2.00)),
+            record(TransactionType::DEPOSIT, 1, 2, Some(1.00)),
+            record(TransactionType::WITHDRAWAL, 1, 3, Some(0.50)),
+            record(TransactionType::WITHDRAWAL, 1, 4, Some(9.00)),
+            record(TransactionType::DISPUTE, 1, 2, None),
+        ]);
+
+        assert_eq!(vec![
+            BalancePoint { tx: 1, available: 2.00, held: 0.00, total: 2.00 },
+            BalancePoint { tx: 2, available: 3.00, held: 0.00, total: 3.00 },
+            BalancePoint { tx: 3, available: 2.50, held: 0.00, total: 2.50 },
+            BalancePoint { tx: 2, available: 1.50, held: 1.00, total: 2.50 },
+        ], service.balance_history(1));
+        assert!(service.balance_history(2).is_empty());
+
+        let mut untracked: TransactionService = Default::default();
+        let _ = untracked.process(record(TransactionType::DEPOSIT, 1, 1, Some(2.00)));
+        assert!(untracked.balance_history(1).is_empty());
+    }
+
+    #[test]
+    fn should_match_sequential_processing_when_processing_in_parallel() {
+        // Deterministic pseudo random numbers, so that failures can be reproduced.
+        let mut seed: u32 = 42;
+        let mut next = move |bound: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % bound
+        };
+        let mut records = Vec::new();
+        for tx in 1..=10_000 {
+            let client = next(50) as u16;
+            let (_type, tx, amount) = match next(10) {
+                0..=4 => (TransactionType::DEPOSIT, tx, Some(next(1_000) as f32 / 10.0)),
+                5..=6 => (TransactionType::WITHDRAWAL, tx, Some(next(1_000) as f32 / 10.0)),
+                7 => (TransactionType::DISPUTE, next(tx) + 1, None),
+                8 => (TransactionType::RESOLVE, next(tx) + 1, None),
+                _ => (TransactionType::CHARGEBACK, next(tx) + 1, None),
+            };
+            records.push(TransactionRecord { _type, client, tx, amount, target_client: None, timestamp: None, currency: None, reference: None });
+        }
+        let report = |service: &TransactionService| {
+            let mut report = Vec::new();
+            service.write_report(&mut report).unwrap();
+            String::from_utf8(report).unwrap()
+        };
+
+        let mut sequential: TransactionService = Default::default();
+        sequential.process_all(records.clone());
+        let parallel = TransactionService::process_parallel(records, 4);
 
-    fn resolve(&mut self, record: TransactionRecord) -> Result<()> {
-        self.process_dispute(&record)
+        assert_eq!(report(&sequential), report(&parallel));
+        assert_eq!(sequential.stats(), parallel.stats());
+        assert_eq!(sequential.history, parallel.history);
+        assert!(sequential.stats().succeeded > 5_000);
+        assert_eq!(Ok(()), parallel.reconcile());
     }
 
-    fn chargeback(&mut self, record: TransactionRecord) -> Result<()> {
-        self.process_dispute(&record)
-    }
-}
+    #[test]
+    fn should_merge_services_with_disjoint_clients() {
+        let mut service: TransactionService = Default::default();
+        let mut other: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = other.process(record(TransactionType::DEPOSIT, 2, 2, Some(2.00)));
 
+        assert_eq!(Ok(()), service.merge(other));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(2.00, service.account_ledger.get(&2).unwrap().available());
+        assert_eq!(vec![2], service.client_transactions(2));
+        assert_eq!(2, service.stats().succeeded);
+        assert!(service.process(record(TransactionType::DISPUTE, 2, 2, None)).is_ok());
+    }
 
     #[test]
-    fn should_be_able_to_deposit_funds() {
+    fn should_reject_merging_services_that_overlap() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.5),
-        };
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
 
-        let result1 = service.process(record1);
+        let mut same_client: TransactionService = Default::default();
+        let _ = same_client.process(record(TransactionType::DEPOSIT, 1, 2, Some(2.00)));
+        assert_eq!(Err(TransactionError::AccountAlreadyExists(1)), service.merge(same_client));
 
-        assert_eq!(Ok(()), result1);
-        assert_eq!(1.5, service.account_ledger.get(&1).unwrap().available);
-        assert_eq!(1.5, service.account_ledger.get(&1).unwrap().total);
+        let mut same_tx: TransactionService = Default::default();
+        let _ = same_tx.process(record(TransactionType::DEPOSIT, 2, 1, Some(2.00)));
+        assert_eq!(Err(TransactionError::DuplicateTransaction(1)), service.merge(same_tx));
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(3.0),
-        };
+        assert!(!service.account_ledger.contains_key(&2));
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().available());
+    }
 
+    #[test]
+    fn should_merge_the_overdraft_limits() {
+        let mut service: TransactionService = Default::default();
+        service.set_overdraft_limit(1, 5.00);
+        service.set_overdraft_limit(3, 1.00);
 
-        let result2 = service.process(record2);
+        let mut conflicting: TransactionService = Default::default();
+        conflicting.set_overdraft_limit(3, 2.00);
+        assert_eq!(Err(TransactionError::ConflictingOverdraftLimit(3)), service.merge(conflicting));
 
-        assert_eq!(Ok(()), result2);
-        assert_eq!(4.5, service.account_ledger.get(&1).unwrap().available);
-        assert_eq!(4.5, service.account_ledger.get(&1).unwrap().total);
+        let mut other: TransactionService = Default::default();
+        other.set_overdraft_limit(2, 10.00);
+        other.set_overdraft_limit(3, 1.00);
+        assert_eq!(Ok(()), service.merge(other));
+
+        assert_eq!(Some(&10.00), service.overdraft_limits.get(&2));
+        assert_eq!(Some(&1.00), service.overdraft_limits.get(&3));
+        let _ = service.open_account(2);
+        assert!(service.process(record(TransactionType::WITHDRAWAL, 2, 1, Some(4.00))).is_ok());
     }
 
     #[test]
-    fn should_be_able_to_withdraw_from_account_with_funds() {
+    fn should_reject_disputes_of_a_finalized_transaction() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(2.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        let _ = service.process(record(TransactionType::CHARGEBACK, 1, 1, None));
 
-        let result1 = service.process(record1);
+        assert!(service.is_finalized(1));
+        assert!(!service.is_finalized(2));
+        assert_eq!(Err(TransactionError::TransactionFinalized), service.process(record(TransactionType::DISPUTE, 1, 1, None)));
+        assert_eq!(Err(TransactionError::LockedAccount), service.process(record(TransactionType::DISPUTE, 1, 2, None)));
 
-        assert_eq!(Ok(()), result1);
-        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().available);
-        assert_eq!(1.50, service.account_ledger.get(&1).unwrap().total);
+        assert!(service.process(record(TransactionType::UNLOCK, 1, 3, None)).is_ok());
+        assert_eq!(Err(TransactionError::TransactionFinalized), service.process(record(TransactionType::DISPUTE, 1, 1, None)));
+        assert_eq!(Err(TransactionError::TransactionFinalized), service.validate(&record(TransactionType::RESOLVE, 1, 1, None)));
+        assert!(service.process(record(TransactionType::DISPUTE, 1, 2, None)).is_ok());
+    }
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::WITHDRAWAL,
-            client: 1,
-            tx: 1,
-            amount: Some(1.40),
-        };
+    #[test]
+    fn should_end_the_report_with_a_summary_when_enabled() {
+        let mut service = TransactionService::builder().with_summary(true).build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.50)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 2, 2, Some(2.25)));
+        let _ = service.process(record(TransactionType::DISPUTE, 2, 2, None));
 
+        let report = service.report_filtered(AccountFilter::All);
 
-        let result2 = service.process(record2);
-        assert_eq!(Ok(()), result2);
-        assert_eq!("0.1000", format!("{:.4}", service.account_ledger.get(&1).unwrap().available));
-        assert_eq!("0.1000", format!("{:.4}", service.account_ledger.get(&1).unwrap().total));
+        assert_eq!(Some("TOTAL,1.5000,2.2500,3.7500,"), report.lines().last());
+        assert_eq!(4, report.lines().count());
+
+        service.set_with_summary(false);
+        assert!(!service.report_filtered(AccountFilter::All).contains("TOTAL"));
     }
 
     #[test]
-    fn should_error_out_if_funds_are_insufficient() {
+    fn should_list_only_the_locked_accounts() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
-            _type: TransactionType::WITHDRAWAL,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
+        for client in 1..=3 {
+            let _ = service.process(record(TransactionType::DEPOSIT, client, client as u32, Some(1.00)));
+        }
+        let _ = service.process(record(TransactionType::DISPUTE, 2, 2, None));
+        let _ = service.process(record(TransactionType::CHARGEBACK, 2, 2, None));
 
-        let result1 = service.process(record1);
+        assert_eq!(vec![2], service.locked_accounts());
+        assert_eq!(3, service.accounts().count());
+    }
 
-        assert_eq!(Err(TransactionError::InvalidAccount), result1);
+    #[test]
+    fn should_restore_the_balances_on_a_successful_appeal() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(2.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        let _ = service.process(record(TransactionType::CHARGEBACK, 1, 1, None));
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.40),
-        };
+        let snapshot = service.process(record(TransactionType::APPEAL, 1, 1, None)).unwrap();
 
-        let _ = service.process(record2);
+        assert_eq!(3.00, snapshot.available);
+        assert_eq!(0.00, snapshot.held);
+        assert_eq!(3.00, snapshot.total);
+        assert!(!snapshot.locked);
+        assert_eq!(Some(&DisputeState::Appealed), service.dispute_ledger.get(&1));
+        assert_eq!(Ok(()), service.reconcile());
+        assert_eq!(Err(TransactionError::TransactionFinalized), service.process(record(TransactionType::DISPUTE, 1, 1, None)));
+        assert_eq!(Err(TransactionError::TransactionNotChargedBack), service.process(record(TransactionType::APPEAL, 1, 1, None)));
+    }
 
-        let record3 = TransactionRecord {
-            _type: TransactionType::WITHDRAWAL,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
+    #[test]
+    fn should_only_appeal_charged_back_transactions() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(2.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 2, None));
 
-        let result3 = service.process(record3);
+        assert_eq!(Err(TransactionError::TransactionNotChargedBack), service.process(record(TransactionType::APPEAL, 1, 1, None)));
+        assert_eq!(Err(TransactionError::TransactionNotChargedBack), service.process(record(TransactionType::APPEAL, 1, 2, None)));
+        let _ = service.process(record(TransactionType::RESOLVE, 1, 2, None));
+        assert_eq!(Err(TransactionError::TransactionNotChargedBack), service.process(record(TransactionType::APPEAL, 1, 2, None)));
+        assert_eq!(3.00, service.account_ledger.get(&1).unwrap().total());
+    }
 
-        assert_eq!(Err(TransactionError::InsufficientFunds), result3);
-        assert_eq!(1.40, service.account_ledger.get(&1).unwrap().total);
-        assert_eq!(1.40, service.account_ledger.get(&1).unwrap().available);
+    #[test]
+    fn should_keep_a_withdrawal_when_its_dispute_is_resolved() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(10.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(4.00)));
+
+        let disputed = service.process(record(TransactionType::DISPUTE, 1, 2, None)).unwrap();
+        assert_eq!((6.00, 4.00, 10.00), (disputed.available, disputed.held, disputed.total));
+        assert_eq!(Ok(()), service.reconcile());
+
+        let resolved = service.process(record(TransactionType::RESOLVE, 1, 2, None)).unwrap();
+        assert_eq!((6.00, 0.00, 6.00), (resolved.available, resolved.held, resolved.total));
+        assert!(!resolved.locked);
+        assert_eq!(Ok(()), service.reconcile());
     }
 
     #[test]
-    fn should_not_raise_dispute_for_invalid_transactions() {
+    fn should_reverse_a_withdrawal_when_its_dispute_is_charged_back() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(10.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(4.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 2, None));
 
-        let _ = service.process(record1);
+        let charged_back = service.process(record(TransactionType::CHARGEBACK, 1, 2, None)).unwrap();
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::DISPUTE,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
-        let result = service.process(record2);
-        assert_eq!(Err(TransactionError::MissingTransaction), result);
+        assert_eq!((10.00, 0.00, 10.00), (charged_back.available, charged_back.held, charged_back.total));
+        assert!(charged_back.locked);
+        assert_eq!(Ok(()), service.reconcile());
     }
 
     #[test]
-    fn should_raise_dispute_for_valid_transaction() {
+    fn should_start_over_after_clearing_the_service() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(2.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
 
-        let _ = service.process(record1);
+        service.clear();
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::DISPUTE,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
-        let result = service.process(record2);
-        assert_eq!(Ok(()), result);
-        let acc = service.account_ledger.get(&1).unwrap();
-        assert_eq!(0.00, acc.available);
-        assert_eq!(1.50, acc.total);
-        assert_eq!(1.50, acc.held);
+        assert_eq!("client,available,held,total,locked\n", service.report_filtered(AccountFilter::All));
+        assert_eq!(EngineStats::default(), service.stats());
+        assert!(service.audit_log().is_empty());
+        assert_eq!(Err(TransactionError::MissingTransaction), service.process(record(TransactionType::DISPUTE, 1, 1, None)));
+        assert!(service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00))).is_ok());
     }
 
     #[test]
-    fn should_resolve_a_valid_dispute() {
+    fn should_propagate_out_of_range_balances_from_the_account() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
+        let _ = service.process(TransactionRecord {
             _type: TransactionType::DEPOSIT,
             client: 1,
             tx: 1,
-            amount: Some(1.50),
-        };
+            amount: Some(1.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        });
 
-        let _ = service.process(record1);
+        assert_eq!(Err(TransactionError::AmountOutOfRange), service.place_hold(1, 2, f32::NAN));
+        assert_eq!(Err(TransactionError::AmountOutOfRange), service.accrue_interest(1, f32::INFINITY));
+        assert_eq!(1.00, service.account_ledger.get(&1).unwrap().available());
+        assert_eq!(0.00, service.account_ledger.get(&1).unwrap().held());
+    }
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::DISPUTE,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
-        let _ = service.process(record2);
+    #[test]
+    fn should_only_reject_disputed_withdrawals_in_strict_mode() {
+        let mut service = TransactionService::builder().strict_dispute(true).build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(10.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(4.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 3, Some(1.00)));
 
-        let record3 = TransactionRecord {
-            _type: TransactionType::RESOLVE,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
-        let result = service.process(record3);
+        assert_eq!(Err(TransactionError::CannotDisputeWithdrawal), service.process(record(TransactionType::DISPUTE, 1, 2, None)));
+        assert!(service.process(record(TransactionType::DISPUTE, 1, 3, None)).is_ok());
+        let _ = service.process(record(TransactionType::RESOLVE, 1, 3, None));
 
-        assert_eq!(Ok(()), result);
-        let acc = service.account_ledger.get(&1).unwrap();
-        assert_eq!(1.50, acc.available);
-        assert_eq!(1.50, acc.total);
-        assert_eq!(0.00, acc.held);
+        service.set_strict_dispute(false);
+        let disputed = service.process(record(TransactionType::DISPUTE, 1, 2, None)).unwrap();
+        assert_eq!(4.00, disputed.held);
     }
 
     #[test]
-    fn should_not_be_able_raise_a_dispute_if_funds_are_insufficient() {
+    fn should_round_trip_the_state_through_bincode() {
         let mut service: TransactionService = Default::default();
-
-        let record1 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.40),
+        let with_metadata = |reference: u32, record| TransactionRecord {
+            timestamp: Some(1_700_000_000_000),
+            currency: Some("EUR".to_string()),
+            reference: Some(format!("REF-{}", reference)),
+            ..record
         };
+        let records = vec![
+            with_metadata(1, record(TransactionType::DEPOSIT, 1, 1, Some(1.50))),
+            with_metadata(2, record(TransactionType::DEPOSIT, 2, 2, Some(2.50))),
+            with_metadata(3, record(TransactionType::DEPOSIT, 3, 3, Some(0.75))),
+            with_metadata(4, record(TransactionType::WITHDRAWAL, 3, 4, Some(0.25))),
+            with_metadata(2, record(TransactionType::DISPUTE, 2, 2, None)),
+            with_metadata(1, record(TransactionType::DISPUTE, 1, 1, None)),
+            with_metadata(1, record(TransactionType::CHARGEBACK, 1, 1, None)),
+        ];
+        let _ = service.process_all(records);
+        service.freeze(3);
 
-        let _ = service.process(record1);
-
-        let record2 = TransactionRecord {
-            _type: TransactionType::WITHDRAWAL,
-            client: 1,
-            tx: 2,
-            amount: Some(1.40),
-        };
+        let restored = TransactionService::from_bincode(&service.to_bincode()).unwrap();
 
-        let _ = service.process(record2);
+        assert_eq!(service.report_filtered(AccountFilter::All), restored.report_filtered(AccountFilter::All));
+        assert_eq!(service.to_json().unwrap(), restored.to_json().unwrap());
+        assert_eq!(vec![1], restored.locked_accounts());
+        assert!(restored.is_disputed(2));
+        assert_eq!(Some("REF-4"), restored.reference(4));
+        assert!(matches!(TransactionService::from_bincode(&[1, 2, 3]), Err(TransactionError::Parse(_))));
+    }
 
-        let record3 = TransactionRecord {
-            _type: TransactionType::DISPUTE,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
+    #[test]
+    fn should_allow_whitelisted_operations_on_a_locked_account() {
+        let mut service = TransactionService::builder()
+            .allow_on_locked(TransactionType::ADJUSTMENT)
+            .build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(2.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        let _ = service.process(record(TransactionType::CHARGEBACK, 1, 1, None));
 
-        let result = service.process(record3);
+        let adjusted = service.process(record(TransactionType::ADJUSTMENT, 1, 3, Some(0.50))).unwrap();
+        assert_eq!(1.50, adjusted.total);
+        assert!(adjusted.locked);
+        assert_eq!(Err(TransactionError::LockedAccount), service.process(record(TransactionType::DEPOSIT, 1, 4, Some(1.00))));
 
-        assert_eq!(Err(TransactionError::InsufficientFunds), result);
+        service.set_allowed_on_locked([]);
+        assert_eq!(Err(TransactionError::LockedAccount), service.process(record(TransactionType::ADJUSTMENT, 1, 5, Some(0.50))));
     }
 
     #[test]
-    fn should_reverse_transaction_for_a_valid_chargeback() {
+    fn should_recompute_the_net_position_of_a_client() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
+        let records = vec![
+            record(TransactionType::DEPOSIT, 1, 1, Some(10.00)),
+            record(TransactionType::DEPOSIT, 1, 2, Some(5.00)),
+            record(TransactionType::WITHDRAWAL, 1, 3, Some(3.00)),
+            record(TransactionType::DISPUTE, 1, 2, None),
+            record(TransactionType::RESOLVE, 1, 2, None),
+            record(TransactionType::DISPUTE, 1, 3, None),
+            record(TransactionType::ADJUSTMENT, 1, 4, Some(-0.50)),
+            record(TransactionType::DISPUTE, 1, 1, Some(2.00)),
+            record(TransactionType::CHARGEBACK, 1, 1, None),
+        ];
+        let _ = service.process_all(records);
 
-        let _ = service.process(record1);
+        let account = service.account_ledger.get(&1).unwrap();
+        assert_eq!(Some(account.available() + account.held()), service.net_position(1));
+        assert_eq!(Some(12.50), service.net_position(1));
+        assert_eq!(None, service.net_position(2));
+    }
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::DISPUTE,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
-        let _ = service.process(record2);
+    #[test]
+    fn should_refund_a_prior_withdrawal() {
+        let mut service: TransactionService = Default::default();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(5.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(3.00)));
 
-        let record3 = TransactionRecord {
-            _type: TransactionType::CHARGEBACK,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
-        let result = service.process(record3);
+        let snapshot = service.process(record(TransactionType::REFUND, 1, 2, Some(1.00))).unwrap();
+        assert_eq!(3.00, snapshot.available);
+        assert_eq!(0.00, snapshot.held);
+        assert!(!snapshot.locked);
 
-        assert_eq!(Ok(()), result);
-        let acc = service.account_ledger.get(&1).unwrap();
-        assert_eq!(0.00, acc.available);
-        assert_eq!(0.00, acc.total);
-        assert_eq!(0.00, acc.held);
-        assert!(acc.locked);
+        let snapshot = service.process(record(TransactionType::REFUND, 1, 2, None)).unwrap();
+        assert_eq!(5.00, snapshot.available);
+        assert_eq!(5.00, snapshot.total);
+        assert_eq!(Ok(()), service.reconcile());
+        assert_eq!(Err(TransactionError::RefundExceedsTransaction), service.process(record(TransactionType::REFUND, 1, 2, Some(0.50))));
     }
 
     #[test]
-    fn should_not_resolve_a_transaction_which_is_not_in_dispute() {
+    fn should_reject_refunds_without_a_prior_withdrawal() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
-        let _ = service.process(record1);
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(5.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 2, Some(1.00)));
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::RESOLVE,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
-        let result = service.process(record2);
+        assert_eq!(Err(TransactionError::MissingTransaction), service.process(record(TransactionType::REFUND, 1, 9, Some(1.00))));
+        assert_eq!(Err(TransactionError::TransactionNotRefundable), service.process(record(TransactionType::REFUND, 1, 1, None)));
+        assert_eq!(Err(TransactionError::TransactionClientMismatch), service.process(record(TransactionType::REFUND, 2, 2, None)));
+        assert_eq!(Err(TransactionError::RefundExceedsTransaction), service.process(record(TransactionType::REFUND, 1, 2, Some(2.00))));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::REFUND, 1, 2, Some(-1.00))));
+        assert_eq!(Err(TransactionError::NonPositiveAmount), service.process(record(TransactionType::REFUND, 1, 2, Some(-0.00))));
+        assert_eq!(4.00, service.account_ledger.get(&1).unwrap().available());
+    }
 
-        assert_eq!(Err(TransactionError::TransactionNotDisputed), result);
+    #[test]
+    fn should_keep_stored_balances_at_scale_when_rounding() {
+        let mut service = TransactionService::builder().round_balances(true).build();
+        for tx in 0..1000 {
+            let _ = service.process(record(TransactionType::DEPOSIT, 1, tx, Some(0.0003)));
+            let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 1000 + tx, Some(0.0002)));
+        }
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 5, None));
+
+        let at_scale = |value: f32| (value * 10_000.0).round() / 10_000.0 == value;
+        let account = service.account_ledger.get(&1).unwrap();
+        assert!(at_scale(account.available()), "{}", account.available());
+        assert!(at_scale(account.held()), "{}", account.held());
+        assert!(at_scale(account.total()), "{}", account.total());
+        assert_eq!(0.1, account.total());
+        assert_eq!(0.0003, account.held());
     }
 
     #[test]
-    fn should_not_process_transaction_for_a_locked_account() {
+    fn should_report_the_status_of_an_account() {
         let mut service: TransactionService = Default::default();
-        let record1 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
-        let _ = service.process(record1);
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 2, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 2, 2, None));
+        let _ = service.process(record(TransactionType::CHARGEBACK, 2, 2, None));
+        let _ = service.process(record(TransactionType::DEPOSIT, 3, 3, Some(1.00)));
+        service.freeze(3);
+        service.freeze(4);
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::DISPUTE,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
-        let _ = service.process(record2);
+        assert_eq!(AccountStatus::Active, service.account_status(1));
+        assert_eq!(AccountStatus::Locked, service.account_status(2));
+        assert_eq!(AccountStatus::Frozen, service.account_status(3));
+        assert_eq!(AccountStatus::Missing, service.account_status(4));
 
-        let record2 = TransactionRecord {
-            _type: TransactionType::CHARGEBACK,
-            client: 1,
-            tx: 1,
-            amount: None,
-        };
-        let _ = service.process(record2);
+        service.freeze(2);
+        service.thaw(3);
+        assert_eq!(AccountStatus::Locked, service.account_status(2));
+        assert_eq!(AccountStatus::Active, service.account_status(3));
+    }
 
-        let record4 = TransactionRecord {
-            _type: TransactionType::DEPOSIT,
-            client: 1,
-            tx: 1,
-            amount: Some(1.50),
-        };
-        let result = service.process(record4);
+    #[test]
+    fn should_skip_evicted_transactions_when_undoing() {
+        let mut service = TransactionService::builder().max_ledger_size(1).build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 3, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 4, Some(1.00)));
 
-        assert_eq!(Err(TransactionError::LockedAccount), result);
+        assert_eq!(Ok(()), service.undo_last());
+        assert_eq!(3.00, service.account_ledger.get(&1).unwrap().total());
+        assert_eq!(Err(TransactionError::MissingTransaction), service.undo_last());
+        assert!(service.history.is_empty());
+
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 5, Some(1.00)));
+        assert_eq!(Ok(()), service.undo_last());
+        assert_eq!(3.00, service.account_ledger.get(&1).unwrap().total());
+    }
+
+    #[test]
+    fn should_evict_the_oldest_transactions_beyond_the_ledger_size() {
+        let mut service = TransactionService::builder().max_ledger_size(2).build();
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 1, Some(1.00)));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 2, Some(2.00)));
+        let _ = service.process(record(TransactionType::DISPUTE, 1, 1, None));
+        let _ = service.process(record(TransactionType::DEPOSIT, 1, 3, Some(3.00)));
+        let _ = service.process(record(TransactionType::WITHDRAWAL, 1, 4, Some(0.50)));
+
+        assert_eq!(2, service.transaction_ledger.len());
+        assert!(service.transaction_ledger.contains_key(&1));
+        assert!(service.transaction_ledger.contains_key(&4));
+        assert_eq!(Err(TransactionError::TransactionExpired), service.process(record(TransactionType::DISPUTE, 1, 2, None)));
+        assert_eq!(Err(TransactionError::TransactionExpired), service.process(record(TransactionType::DISPUTE, 1, 3, None)));
+        assert_eq!(Ok(()), service.reconcile());
+
+        let restored = TransactionService::restore(service.snapshot());
+        assert_eq!(Err(TransactionError::TransactionExpired), restored.validate(&record(TransactionType::DISPUTE, 1, 2, None)));
+        assert_eq!(Ok(()), restored.reconcile());
+    }
+
+    #[test]
+    fn should_keep_every_ledger_at_the_cap() {
+        let mut service = TransactionService::builder()
+            .max_ledger_size(5)
+            .idempotent(true)
+            .build();
+        let sizes = |service: &TransactionService| [
+            service.transaction_ledger.len(),
+            service.history.len(),
+            service.client_transactions.values().map(Vec::len).sum(),
+            service.applied_records.len(),
+            service.ledger_order.len(),
+            service.expired_transactions.len(),
+            service.expired_order.len(),
+        ];
+        for tx in 1..=100 {
+            let _ = service.process(record(TransactionType::DEPOSIT, (tx % 3) as u16, tx, Some(1.00)));
+        }
+        assert_eq!([5; 7], sizes(&service));
+
+        let mut restored = TransactionService::restore(service.snapshot());
+        for tx in 101..=200 {
+            let _ = restored.process(record(TransactionType::DEPOSIT, (tx % 3) as u16, tx, Some(1.00)));
+        }
+        assert_eq!([5; 7], sizes(&restored));
+        assert_eq!(Err(TransactionError::TransactionExpired), restored.validate(&record(TransactionType::DISPUTE, 2, 194, None)));
+        assert_eq!(Err(TransactionError::MissingTransaction), restored.validate(&record(TransactionType::DISPUTE, 2, 2, None)));
+        assert_eq!(Ok(()), restored.reconcile());
     }
 }
\ No newline at end of file
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use log::LevelFilter;
+use crate::{Account, AccountStore, FeePolicy, MissingAccountPolicy, RoundingMode, TransactionService, TransactionType};
+use super::settings::Settings;
+
+/// Configures the optional behaviours of a `TransactionService`.
+/// Any behaviour that is not configured keeps its default,
+/// so `TransactionServiceBuilder::new().build()` is equivalent
+/// to `TransactionService::default()`.
+#[derive(Default)]
+pub struct TransactionServiceBuilder {
+    settings: Settings,
+    overdraft_limits: HashMap<u16, f32>,
+}
+
+impl TransactionServiceBuilder {
+    /// Starts with every behaviour set to its default.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Changes how money is rounded in the report.
+    pub fn rounding_mode(mut self, rounding_mode: RoundingMode) -> Self {
+        self.settings.rounding_mode = rounding_mode;
+        self
+    }
+
+    /// Changes the number of decimals used for money, 4 by default.
+    /// Amounts with more decimals than the scale are rejected.
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.settings.scale = scale;
+        self
+    }
+
+    /// Changes the tolerance used when checking whether funds cover an amount,
+    /// 0.00001 by default. Shortfalls within it are put down to `f32` rounding.
+    pub fn epsilon(mut self, epsilon: f32) -> Self {
+        self.settings.epsilon = epsilon;
+        self
+    }
+
+    /// Caps the amount of a single withdrawal.
+    pub fn max_withdrawal(mut self, max_withdrawal: f32) -> Self {
+        self.settings.max_withdrawal = Some(max_withdrawal);
+        self
+    }
+
+    /// Charges a fee on top of every withdrawal, credited to the Account of `fee_client`.
+    /// Withdrawals of `fee_client` itself are not charged a fee.
+    pub fn withdrawal_fee(mut self, policy: FeePolicy, fee_client: u16) -> Self {
+        self.settings.withdrawal_fee = policy;
+        self.settings.fee_client = fee_client;
+        self
+    }
+
+    /// Caps the number of disputes a client may have open at once.
+    pub fn max_open_disputes(mut self, max_open_disputes: usize) -> Self {
+        self.settings.max_open_disputes = Some(max_open_disputes);
+        self
+    }
+
+    /// Caps the number of Accounts, so that a flood of unique clients
+    /// can't exhaust memory. Existing Accounts keep transacting.
+    pub fn max_accounts(mut self, max_accounts: usize) -> Self {
+        self.settings.max_accounts = Some(max_accounts);
+        self
+    }
+
+    /// Caps the number of deposits and withdrawals kept for disputes, so that
+    /// a long-running service doesn't grow without bound. Beyond the cap the
+    /// oldest transactions which aren't under dispute are evicted, and later
+    /// records referencing them fail with `TransactionError::TransactionExpired`.
+    /// Only as many evicted transactions as the cap are remembered; records
+    /// referencing older ones fail like records of unknown transactions.
+    pub fn max_ledger_size(mut self, max_ledger_size: usize) -> Self {
+        self.settings.max_ledger_size = Some(max_ledger_size);
+        self
+    }
+
+    /// Records an alert in the audit log whenever a dispute pushes
+    /// an Account's held amount above the threshold.
+    pub fn held_alert_threshold(mut self, threshold: f32) -> Self {
+        self.settings.held_alert_threshold = Some(threshold);
+        self
+    }
+
+    /// Allows an operation on locked Accounts, e.g. `ADJUSTMENT` for a
+    /// court-ordered credit. Can be called for several operations.
+    pub fn allow_on_locked(mut self, _type: TransactionType) -> Self {
+        self.settings.allowed_on_locked.insert(_type);
+        self
+    }
+
+    /// Only allows deposits to be disputed, for rulesets where disputing a
+    /// withdrawal fails with `CannotDisputeWithdrawal`.
+    pub fn strict_dispute(mut self, strict_dispute: bool) -> Self {
+        self.settings.strict_dispute = strict_dispute;
+        self
+    }
+
+    /// Makes replayed deposits and withdrawals succeed without being
+    /// applied again, for pipelines that may deliver a record twice.
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.settings.idempotent = idempotent;
+        self
+    }
+
+    /// Rounds the balances of the Accounts an operation touched to the
+    /// scale after every operation, so that the error of f32 arithmetic
+    /// doesn't build up in the stored balances. Off by default.
+    pub fn round_balances(mut self, round_balances: bool) -> Self {
+        self.settings.round_balances = round_balances;
+        self
+    }
+
+    /// Keeps the balances of every client after each of their transactions,
+    /// so that `TransactionService::balance_history` can chart them.
+    /// This costs memory for every transaction, so it is off by default.
+    pub fn track_balance_history(mut self, track: bool) -> Self {
+        self.settings.track_balance_history = track;
+        self
+    }
+
+    /// Ends the CSV report with a `TOTAL` row summing the available, held and
+    /// total amounts of every reported Account. Off by default, since parsers
+    /// of the report may expect a row per client only.
+    pub fn with_summary(mut self, with_summary: bool) -> Self {
+        self.settings.with_summary = with_summary;
+        self
+    }
+
+    /// Changes how withdrawals for clients without an Account are handled.
+    pub fn missing_account_policy(mut self, policy: MissingAccountPolicy) -> Self {
+        self.settings.missing_account_policy = policy;
+        self
+    }
+
+    /// Limits how verbose the service logs, independently of how the
+    /// global logger is configured. `LevelFilter::Off` silences it.
+    pub fn log_level(mut self, log_level: LevelFilter) -> Self {
+        self.settings.log_level = log_level;
+        self
+    }
+
+    /// Allows the client to withdraw until their available balance
+    /// reaches `-limit`.
+    pub fn overdraft_limit(mut self, client: u16, limit: f32) -> Self {
+        self.overdraft_limits.insert(client, limit);
+        self
+    }
+
+    /// Builds a service which keeps the Accounts in memory.
+    pub fn build(self) -> TransactionService {
+        self.build_with_store(HashMap::<u16, Account>::new())
+    }
+
+    /// Builds a service which keeps the Accounts in the given store.
+    pub fn build_with_store<S: AccountStore>(self, store: S) -> TransactionService<S> {
+        TransactionService::configured(store, self.settings, self.overdraft_limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TransactionError, TransactionRecord, TransactionType};
+
+    #[test]
+    fn should_build_a_service_with_the_configured_withdrawal_limit() {
+        let mut service = TransactionServiceBuilder::new()
+            .max_withdrawal(1.00)
+            .build();
+        let deposit = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(5.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(deposit);
+
+        let withdrawal = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 2,
+            amount: Some(2.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        assert_eq!(Err(TransactionError::WithdrawalLimitExceeded), service.process(withdrawal));
+    }
+
+    #[test]
+    fn should_build_a_default_service_without_any_configuration() {
+        let mut service = TransactionServiceBuilder::new().build();
+        let deposit = TransactionRecord {
+            _type: TransactionType::DEPOSIT,
+            client: 1,
+            tx: 1,
+            amount: Some(5.00),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+        let _ = service.process(deposit);
+
+        let withdrawal = TransactionRecord {
+            _type: TransactionType::WITHDRAWAL,
+            client: 1,
+            tx: 2,
+            amount: Some(5.50),
+            target_client: None,
+            timestamp: None,
+            currency: None,
+            reference: None,
+        };
+
+        assert_eq!(Err(TransactionError::InsufficientFunds), service.process(withdrawal));
+    }
+}
\ No newline at end of file
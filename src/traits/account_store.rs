@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use crate::Account;
+
+
+/// Defines the storage used by `TransactionService` to keep a track of
+/// the Accounts, which allows plugging in backends other than the
+/// default in-memory `HashMap`.
+pub trait AccountStore {
+    /// fetches the account of a client.
+    fn get(&self, client: &u16) -> Option<&Account>;
+    /// stores the account of a client, returning the account it replaced.
+    fn insert(&mut self, client: u16, account: Account) -> Option<Account>;
+    /// removes the account of a client, returning it if it existed.
+    fn remove(&mut self, client: &u16) -> Option<Account>;
+    /// iterates over every stored account in no particular order.
+    fn iter(&self) -> impl Iterator<Item = (&u16, &Account)>;
+
+    /// checks whether a client has an account.
+    fn contains(&self, client: &u16) -> bool {
+        self.get(client).is_some()
+    }
+
+    /// removes every account.
+    fn clear(&mut self) {
+        let clients: Vec<u16> = self.iter().map(|(client, _)| *client).collect();
+        for client in clients {
+            self.remove(&client);
+        }
+    }
+}
+
+impl AccountStore for HashMap<u16, Account> {
+    fn get(&self, client: &u16) -> Option<&Account> {
+        HashMap::get(self, client)
+    }
+
+    fn insert(&mut self, client: u16, account: Account) -> Option<Account> {
+        HashMap::insert(self, client, account)
+    }
+
+    fn remove(&mut self, client: &u16) -> Option<Account> {
+        HashMap::remove(self, client)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&u16, &Account)> {
+        HashMap::iter(self)
+    }
+
+    fn clear(&mut self) {
+        HashMap::clear(self)
+    }
+}
\ No newline at end of file
@@ -1,3 +1,5 @@
 mod transaction;
+mod account_store;
 
-pub use transaction::Transaction;
\ No newline at end of file
+pub use transaction::Transaction;
+pub use account_store::AccountStore;
\ No newline at end of file
@@ -1,17 +1,141 @@
-use crate::Result;
+use crate::{AccountSnapshot, Result, TransactionError};
 
 
 /// Defines the behaviour and possible operations you could
 /// have in a transaction where the input record is of type T.
+/// Every operation returns the state of the Account it changed.
+/// Operations added after the original five have default implementations
+/// failing with `TransactionError::InvalidOperation`, so that implementors
+/// which don't support them keep compiling.
 pub trait Transaction<T> {
     /// puts money into an account.
-    fn deposit(&mut self, record: T) -> Result<()>;
+    fn deposit(&mut self, record: T) -> Result<AccountSnapshot>;
     /// takes money away from an account.
-    fn withdrawal(&mut self, record: T) -> Result<()>;
+    fn withdrawal(&mut self, record: T) -> Result<AccountSnapshot>;
     /// Raises a dispute for one of the older transactions.
-    fn dispute(&mut self, record: T) -> Result<()>;
+    fn dispute(&mut self, record: T) -> Result<AccountSnapshot>;
     /// Ends an existing dispute and reverts the held money.
-    fn resolve(&mut self, record: T) -> Result<()>;
+    fn resolve(&mut self, record: T) -> Result<AccountSnapshot>;
     /// Reverts the transaction under dispute and locks the account
-    fn chargeback(&mut self, record: T) -> Result<()>;
-}
\ No newline at end of file
+    fn chargeback(&mut self, record: T) -> Result<AccountSnapshot>;
+    /// Reverts the transaction under dispute but keeps the account active.
+    fn soft_chargeback(&mut self, _record: T) -> Result<AccountSnapshot> {
+        Err(TransactionError::InvalidOperation)
+    }
+    /// puts a resolved dispute back under dispute.
+    fn reopen(&mut self, _record: T) -> Result<AccountSnapshot> {
+        Err(TransactionError::InvalidOperation)
+    }
+    /// reverses a chargeback after a successful appeal.
+    fn appeal(&mut self, _record: T) -> Result<AccountSnapshot> {
+        Err(TransactionError::InvalidOperation)
+    }
+    /// returns the money of an earlier withdrawal to the account.
+    fn refund(&mut self, _record: T) -> Result<AccountSnapshot> {
+        Err(TransactionError::InvalidOperation)
+    }
+    /// moves money from one account to another.
+    fn transfer(&mut self, _record: T) -> Result<AccountSnapshot> {
+        Err(TransactionError::InvalidOperation)
+    }
+    /// reinstates an account locked by a chargeback.
+    fn unlock(&mut self, _record: T) -> Result<AccountSnapshot> {
+        Err(TransactionError::InvalidOperation)
+    }
+    /// removes an account which no longer has any funds.
+    fn close(&mut self, _record: T) -> Result<AccountSnapshot> {
+        Err(TransactionError::InvalidOperation)
+    }
+    /// corrects the balance of an account by a signed amount.
+    fn adjustment(&mut self, _record: T) -> Result<AccountSnapshot> {
+        Err(TransactionError::InvalidOperation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transaction;
+    use crate::{AccountSnapshot, Result, TransactionError, TransactionRecord, TransactionService, TransactionType};
+
+    /// Implements only the original operations, like an implementor
+    /// written before the later ones were added.
+    struct Ledger;
+
+    impl Transaction<u32> for Ledger {
+        fn deposit(&mut self, _: u32) -> Result<AccountSnapshot> {
+            Err(TransactionError::MissingAmount)
+        }
+        fn withdrawal(&mut self, _: u32) -> Result<AccountSnapshot> {
+            Err(TransactionError::MissingAmount)
+        }
+        fn dispute(&mut self, _: u32) -> Result<AccountSnapshot> {
+            Err(TransactionError::MissingTransaction)
+        }
+        fn resolve(&mut self, _: u32) -> Result<AccountSnapshot> {
+            Err(TransactionError::MissingTransaction)
+        }
+        fn chargeback(&mut self, _: u32) -> Result<AccountSnapshot> {
+            Err(TransactionError::MissingTransaction)
+        }
+    }
+
+    fn record(_type: TransactionType, client: u16, tx: u32, amount: Option<f32>) -> TransactionRecord {
+        TransactionRecord {
+            _type,
+            client,
+            tx,
+            amount,
+            target_client: Some(2),
+            timestamp: None,
+            currency: None,
+            reference: None,
+        }
+    }
+
+    /// Runs every operation of the trait through a generic implementor.
+    fn run_all<T: Transaction<TransactionRecord>>(service: &mut T) -> Vec<Result<AccountSnapshot>> {
+        vec![
+            service.deposit(record(TransactionType::DEPOSIT, 1, 1, Some(1.00))),
+            service.deposit(record(TransactionType::DEPOSIT, 1, 2, Some(0.50))),
+            service.withdrawal(record(TransactionType::WITHDRAWAL, 1, 3, Some(0.25))),
+            service.refund(record(TransactionType::REFUND, 1, 3, Some(0.05))),
+            service.dispute(record(TransactionType::DISPUTE, 1, 1, None)),
+            service.resolve(record(TransactionType::RESOLVE, 1, 1, None)),
+            service.reopen(record(TransactionType::REOPEN, 1, 1, None)),
+            service.soft_chargeback(record(TransactionType::SOFT_CHARGEBACK, 1, 1, None)),
+            service.transfer(record(TransactionType::TRANSFER, 1, 4, Some(0.10))),
+            service.adjustment(record(TransactionType::ADJUSTMENT, 1, 5, Some(1.00))),
+            service.dispute(record(TransactionType::DISPUTE, 1, 2, None)),
+            service.chargeback(record(TransactionType::CHARGEBACK, 1, 2, None)),
+            service.appeal(record(TransactionType::APPEAL, 1, 2, None)),
+            service.unlock(record(TransactionType::UNLOCK, 1, 6, None)),
+            service.deposit(record(TransactionType::DEPOSIT, 3, 7, Some(0.50))),
+            service.withdrawal(record(TransactionType::WITHDRAWAL, 3, 8, Some(0.50))),
+            service.close(record(TransactionType::CLOSE, 3, 9, None)),
+        ]
+    }
+
+    #[test]
+    fn should_support_every_operation_on_the_service() {
+        let mut service: TransactionService = Default::default();
+
+        let results = run_all(&mut service);
+
+        assert!(results.iter().all(|result| result.is_ok()), "{:?}", results);
+    }
+
+    #[test]
+    fn should_fail_later_operations_by_default() {
+        let mut ledger = Ledger;
+
+        assert_eq!(Err(TransactionError::InvalidOperation), ledger.soft_chargeback(1));
+        assert_eq!(Err(TransactionError::InvalidOperation), ledger.reopen(1));
+        assert_eq!(Err(TransactionError::InvalidOperation), ledger.appeal(1));
+        assert_eq!(Err(TransactionError::InvalidOperation), ledger.refund(1));
+        assert_eq!(Err(TransactionError::InvalidOperation), ledger.transfer(1));
+        assert_eq!(Err(TransactionError::InvalidOperation), ledger.unlock(1));
+        assert_eq!(Err(TransactionError::InvalidOperation), ledger.close(1));
+        assert_eq!(Err(TransactionError::InvalidOperation), ledger.adjustment(1));
+        assert_eq!(Err(TransactionError::MissingAmount), ledger.deposit(1));
+    }
+}
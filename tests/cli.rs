@@ -0,0 +1,80 @@
+use std::{env, fs};
+use std::process::Command;
+
+fn run(fixture: &str) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_transactions_engine"))
+        .arg(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), fixture))
+        .output()
+        .expect("failed to run the binary");
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn should_parse_fields_surrounded_by_whitespace() {
+    let lines = run("whitespace.csv");
+
+    assert_eq!(vec![
+        "client,available,held,total,locked",
+        "1,0.7500,0.0000,0.7500,false",
+        "2,0.0000,2.5000,2.5000,false",
+    ], lines);
+}
+
+#[test]
+fn should_decompress_gzipped_input() {
+    let lines = run("compressed.csv.gz");
+
+    assert_eq!(vec![
+        "client,available,held,total,locked",
+        "1,1.5000,0.0000,1.5000,false",
+        "2,3.5000,0.0000,3.5000,false",
+    ], lines);
+}
+#[test]
+fn should_write_the_report_to_the_output_file_in_the_requested_format() {
+    let path = env::temp_dir().join(format!("transactions_engine_report_{}.json", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_transactions_engine"))
+        .arg(format!("{}/tests/fixtures/whitespace.csv", env!("CARGO_MANIFEST_DIR")))
+        .args(["--output", path.to_str().unwrap(), "--format", "json"])
+        .output()
+        .expect("failed to run the binary");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let report = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        r#"[{"client":1,"available":"0.7500","held":"0.0000","total":"0.7500","locked":false},{"client":2,"available":"0.0000","held":"2.5000","total":"2.5000","locked":false}]"#,
+        report.trim_end()
+    );
+}
+
+#[test]
+fn should_reject_an_unknown_report_format() {
+    let output = Command::new(env!("CARGO_BIN_EXE_transactions_engine"))
+        .arg(format!("{}/tests/fixtures/whitespace.csv", env!("CARGO_MANIFEST_DIR")))
+        .args(["--format", "xml"])
+        .output()
+        .expect("failed to run the binary");
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("unknown report format xml"));
+}
+
+#[test]
+fn should_print_the_usage_without_an_input_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_transactions_engine"))
+        .output()
+        .expect("failed to run the binary");
+
+    assert_eq!(Some(2), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("usage: transactions_engine"));
+}
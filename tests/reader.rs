@@ -0,0 +1,345 @@
+use transactions_engine::{AuditEvent, process_files, process_files_with, process_json, process_json_with, process_reader, records_from_reader, records_from_reader_with, run_csv, ReaderOptions, TransactionError, TransactionService, TransactionType};
+
+#[test]
+fn should_process_transactions_from_a_byte_slice() {
+    let input = b"type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+deposit,1,3,2.0
+withdrawal,1,4,1.5
+withdrawal,2,5,3.0
+";
+    let mut service: TransactionService = Default::default();
+
+    process_reader(&input[..], &mut service).unwrap();
+
+    let mut balances: Vec<(u16, f32, f32)> = service.accounts()
+        .map(|(client, acc)| (*client, acc.available(), acc.total()))
+        .collect();
+    balances.sort_by_key(|(client, _, _)| *client);
+    assert_eq!(vec![(1, 1.5, 1.5), (2, 2.0, 2.0)], balances);
+}
+
+#[test]
+fn should_process_a_json_array_like_the_equivalent_csv() {
+    let csv = b"type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+withdrawal,1,3,0.5
+dispute,2,2,
+";
+    let json = br#"[
+        {"type": "deposit", "client": 1, "tx": 1, "amount": 1.0},
+        {"type": "deposit", "client": 2, "tx": 2, "amount": 2.0},
+        {"type": "withdrawal", "client": 1, "tx": 3, "amount": 0.5},
+        {"type": "dispute", "client": 2, "tx": 2}
+    ]"#;
+    let mut csv_service: TransactionService = Default::default();
+    let mut json_service: TransactionService = Default::default();
+
+    process_reader(&csv[..], &mut csv_service).unwrap();
+    process_json(&json[..], &mut json_service).unwrap();
+
+    let mut csv_report = Vec::new();
+    let mut json_report = Vec::new();
+    csv_service.write_report(&mut csv_report).unwrap();
+    json_service.write_report(&mut json_report).unwrap();
+    assert_eq!("client,available,held,total,locked
+1,0.5000,0.0000,0.5000,false
+2,0.0000,2.0000,2.0000,false
+", String::from_utf8(json_report.clone()).unwrap());
+    assert_eq!(csv_report, json_report);
+}
+
+#[test]
+fn should_report_malformed_rows_as_parse_errors() {
+    let input = b"type,client,tx,amount
+deposit,1,1,one
+";
+    let mut service: TransactionService = Default::default();
+
+    let result = process_reader(&input[..], &mut service);
+
+    assert!(matches!(result, Err(TransactionError::Parse(_))), "{:?}", result);
+    assert_eq!(0, service.accounts().count());
+}
+
+#[test]
+fn should_iterate_over_the_records_of_a_reader() {
+    let input = b"type,client,tx,amount
+deposit,1,1,1.0
+withdrawal,2,2,0.5
+dispute,1,1,
+";
+
+    let records: Vec<(TransactionType, u16, u32, Option<f32>)> = records_from_reader(&input[..])
+        .map(|record| record.unwrap())
+        .map(|record| (record._type, record.client, record.tx, record.amount))
+        .collect();
+
+    assert_eq!(vec![
+        (TransactionType::DEPOSIT, 1, 1, Some(1.0)),
+        (TransactionType::WITHDRAWAL, 2, 2, Some(0.5)),
+        (TransactionType::DISPUTE, 1, 1, None),
+    ], records);
+}
+
+#[test]
+fn should_merge_several_files_into_one_run() {
+    let paths: Vec<String> = ["day1.csv", "day2.csv"].iter()
+        .map(|fixture| format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), fixture))
+        .collect();
+    let mut service: TransactionService = Default::default();
+
+    process_files(&paths, &mut service).unwrap();
+
+    let mut report = Vec::new();
+    service.write_report(&mut report).unwrap();
+    assert_eq!("client,available,held,total,locked
+1,1.5000,0.0000,1.5000,false
+2,1.5000,1.0000,2.5000,false
+", String::from_utf8(report).unwrap());
+}
+
+#[test]
+fn should_process_a_semicolon_delimited_file() {
+    let paths = vec![format!("{}/tests/fixtures/semicolon.csv", env!("CARGO_MANIFEST_DIR"))];
+    let mut service: TransactionService = Default::default();
+
+    process_files_with(&paths, &mut service, &ReaderOptions::new().delimiter(b';')).unwrap();
+
+    let mut report = Vec::new();
+    service.write_report(&mut report).unwrap();
+    assert_eq!("client,available,held,total,locked
+1,2.0000,0.0000,2.0000,false
+2,1.0000,0.0000,1.0000,false
+", String::from_utf8(report).unwrap());
+}
+
+#[test]
+fn should_read_tab_separated_records() {
+    let input = b"type\tclient\ttx\tamount\ndeposit\t1\t1\t1.5\n";
+
+    let records: Vec<_> = records_from_reader_with(&input[..], &ReaderOptions::new().delimiter(b'\t'))
+        .map(|record| record.map(|record| (record.client, record.amount)))
+        .collect();
+
+    assert_eq!(vec![Ok((1, Some(1.5)))], records);
+}
+
+#[test]
+fn should_carry_timestamps_through_to_the_audit_log() {
+    let input = b"type,client,tx,amount,timestamp
+deposit,1,1,1.0,1700000002000
+deposit,1,2,2.0,1700000001000
+withdrawal,1,3,0.5,
+";
+    let mut service: TransactionService = Default::default();
+
+    process_reader(&input[..], &mut service).unwrap();
+
+    let timestamps = |events: Vec<&AuditEvent>| -> Vec<(u32, Option<i64>)> {
+        events.into_iter()
+            .filter_map(|event| match event {
+                AuditEvent::Transaction { tx, timestamp, .. } => Some((*tx, *timestamp)),
+                _ => None,
+            })
+            .collect()
+    };
+    assert_eq!(vec![(1, Some(1700000002000)), (2, Some(1700000001000)), (3, None)],
+               timestamps(service.audit_log().iter().collect()));
+    assert_eq!(vec![(2, Some(1700000001000)), (3, None), (1, Some(1700000002000))],
+               timestamps(service.audit_log_by_time()));
+}
+
+#[test]
+fn should_return_the_report_for_a_csv_string() {
+    let input = "type,client,tx,amount
+deposit,2,1,3.0
+deposit,1,2,1.25
+withdrawal,2,3,1.0
+";
+
+    assert_eq!(Ok("client,available,held,total,locked
+1,1.2500,0.0000,1.2500,false
+2,2.0000,0.0000,2.0000,false
+".to_string()), run_csv(input));
+}
+#[test]
+fn should_reject_records_in_a_different_currency_than_the_account() {
+    let input = b"type,client,tx,amount,currency
+deposit,1,1,2.0,USD
+deposit,1,2,1.0,EUR
+withdrawal,1,3,0.5,
+withdrawal,1,4,0.5,USD
+";
+    let mut service: TransactionService = Default::default();
+
+    process_reader(&input[..], &mut service).unwrap();
+
+    let (_, account) = service.accounts().next().unwrap();
+    assert_eq!(Some("USD"), account.currency());
+    assert_eq!(1.0, account.total());
+}
+
+#[test]
+fn should_skip_a_failing_row_by_default() {
+    let paths = vec![format!("{}/tests/fixtures/invalid_row.csv", env!("CARGO_MANIFEST_DIR"))];
+    let mut service: TransactionService = Default::default();
+
+    assert_eq!(Ok(()), process_files_with(&paths, &mut service, &ReaderOptions::new()));
+
+    let (_, account) = service.accounts().next().unwrap();
+    assert_eq!(3.0, account.total());
+}
+
+#[test]
+fn should_stop_at_the_first_failing_row_when_failing_fast() {
+    let paths = vec![format!("{}/tests/fixtures/invalid_row.csv", env!("CARGO_MANIFEST_DIR"))];
+    let mut service: TransactionService = Default::default();
+
+    let result = process_files_with(&paths, &mut service, &ReaderOptions::new().fail_fast(true));
+
+    assert_eq!(Err(TransactionError::InsufficientFunds), result);
+    let (_, account) = service.accounts().next().unwrap();
+    assert_eq!(1.0, account.total());
+}
+
+#[test]
+fn should_only_accept_plain_decimal_amounts() {
+    let amounts = |input: &[u8]| -> Vec<Option<Option<f32>>> {
+        records_from_reader(input)
+            .map(|record| record.ok().map(|record| record.amount))
+            .collect()
+    };
+
+    assert_eq!(vec![Some(Some(1000.0)), Some(Some(-0.5)), Some(None)],
+               amounts(b"type,client,tx,amount\ndeposit,1,1,1000.0\nadjustment,1,2,-.5\ndispute,1,1,\n"));
+    assert_eq!(vec![None, None, None, None],
+               amounts(b"type,client,tx,amount\ndeposit,1,1,1e3\ndeposit,1,2,inf\ndeposit,1,3,1.0.0\ndeposit,1,4,-\n"));
+}
+
+#[test]
+fn should_only_accept_plain_decimal_amounts_in_json() {
+    let mut service: TransactionService = Default::default();
+
+    let result = process_json(&br#"[{"type": "deposit", "client": 1, "tx": 1, "amount": 1e3}]"#[..], &mut service);
+
+    assert!(matches!(result, Err(TransactionError::Parse(_))), "{:?}", result);
+    assert_eq!(0, service.accounts().count());
+    assert_eq!(Ok(()), process_json(&br#"[{"type": "deposit", "client": 1, "tx": 1, "amount": 1000.0},
+                                          {"type": "dispute", "client": 1, "tx": 1, "amount": null}]"#[..], &mut service));
+    assert_eq!(1000.0, service.accounts().next().unwrap().1.total());
+}
+
+#[test]
+fn should_reject_rows_of_an_unknown_type_on_their_own() {
+    let input = b"type,client,tx,amount
+deposit,1,1,1.0
+rebate,1,2,0.5
+deposit,1,3,2.0
+";
+    let mut service: TransactionService = Default::default();
+    let records: Vec<_> = records_from_reader(&input[..]).collect::<Result<_, _>>().unwrap();
+
+    let outcomes = service.process_all(records);
+
+    assert_eq!(Err(TransactionError::UnsupportedTransactionType("rebate".to_string())), outcomes[1].1);
+    let (_, account) = service.accounts().next().unwrap();
+    assert_eq!(3.0, account.total());
+}
+
+#[test]
+fn should_name_client_ids_that_are_out_of_range() {
+    let csv = b"type,client,tx,amount
+deposit,1,1,1.0
+deposit,70000,2,1.0
+";
+    let json = br#"[{"type": "deposit", "client": 70000, "tx": 1, "amount": 1.0}]"#;
+    let mut service: TransactionService = Default::default();
+
+    assert_eq!(Err(TransactionError::ClientIdOutOfRange("70000".to_string())), process_reader(&csv[..], &mut service));
+    assert_eq!(1, service.accounts().count());
+    assert_eq!(Err(TransactionError::ClientIdOutOfRange("70000".to_string())), process_json(&json[..], &mut service));
+    assert!(matches!(records_from_reader(&b"type,client,tx,amount\ndeposit,-1,1,1.0\n"[..]).next(),
+                     Some(Err(TransactionError::ClientIdOutOfRange(_)))));
+}
+
+#[test]
+fn should_carry_references_through_to_the_audit_log() {
+    let input = b"type,client,tx,amount,reference
+deposit,1,1,1.0,INV-001
+deposit,1,2,2.0,
+";
+    let mut service: TransactionService = Default::default();
+
+    process_reader(&input[..], &mut service).unwrap();
+
+    assert_eq!(Some("INV-001"), service.reference(1));
+    assert_eq!(None, service.reference(2));
+    let references: Vec<Option<String>> = service.audit_log().iter()
+        .map(|event| match event {
+            AuditEvent::Transaction { reference, .. } => reference.clone(),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(vec![Some("INV-001".to_string()), None], references);
+}
+
+#[test]
+fn should_reject_amounts_with_too_many_decimal_places() {
+    let amounts = |input: &[u8], options: &ReaderOptions| -> Vec<Result<Option<f32>, TransactionError>> {
+        records_from_reader_with(input, options)
+            .map(|record| record.map(|record| record.amount))
+            .collect()
+    };
+    let input = b"type,client,tx,amount\ndeposit,1,1,1.23456\ndeposit,1,2,1.2345\ndeposit,1,3,1.234500\n";
+
+    assert_eq!(vec![Err(TransactionError::AmountExceedsScale), Ok(Some(1.2345)), Ok(Some(1.2345))],
+               amounts(input, &ReaderOptions::new()));
+    assert_eq!(vec![Ok(Some(1.23456)), Ok(Some(1.2345)), Ok(Some(1.2345))],
+               amounts(input, &ReaderOptions::new().max_decimal_places(5)));
+}
+
+#[test]
+fn should_allow_as_many_decimal_places_as_the_scale() {
+    let csv = b"type,client,tx,amount\ndeposit,1,1,0.12345\n";
+    let json = br#"[{"type": "deposit", "client": 2, "tx": 2, "amount": 0.12345}]"#;
+    let mut service = TransactionService::builder().scale(8).build();
+
+    assert_eq!(Ok(()), process_reader(&csv[..], &mut service));
+    assert_eq!(Ok(()), process_json(&json[..], &mut service));
+    assert_eq!(2, service.accounts().count());
+
+    let mut service: TransactionService = Default::default();
+    assert_eq!(Err(TransactionError::AmountExceedsScale), process_reader(&csv[..], &mut service));
+    assert_eq!(Err(TransactionError::AmountExceedsScale), process_json_with(&json[..], &mut service, &ReaderOptions::new().max_decimal_places(5)));
+    assert_eq!(0, service.accounts().count());
+}
+
+#[test]
+fn should_stop_json_at_the_first_failure_with_fail_fast() {
+    let json = br#"[{"type": "withdrawal", "client": 1, "tx": 1, "amount": 1.0},
+                    {"type": "deposit", "client": 1, "tx": 2, "amount": 1.0}]"#;
+    let mut service: TransactionService = Default::default();
+
+    let result = process_json_with(&json[..], &mut service, &ReaderOptions::new().fail_fast(true));
+
+    assert!(result.is_err());
+    assert_eq!(0, service.accounts().count());
+}
+
+#[test]
+fn should_report_the_progress_every_interval() {
+    let paths: Vec<String> = ["day1.csv", "day2.csv"].iter()
+        .map(|fixture| format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), fixture))
+        .collect();
+    let reported = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = reported.clone();
+    let options = ReaderOptions::new().progress(2, move |processed| sink.lock().unwrap().push(processed));
+    let mut service: TransactionService = Default::default();
+
+    process_files_with(&paths, &mut service, &options).unwrap();
+
+    assert_eq!(vec![2, 4], *reported.lock().unwrap());
+}